@@ -0,0 +1,113 @@
+use std::{
+    env,
+    fs::{create_dir_all, read_to_string, write},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{time_arg, CacheArgs, ClientArgs};
+
+/// On-disk cache of listings, stored as JSON files in the `cache` dir next to the config
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// None if caching is disabled
+    dir: Option<PathBuf>,
+    ttl: Duration,
+    refresh: bool,
+    /// profile and region, so listings of different accounts don't mix
+    scope: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    created: i64,
+    value: T,
+}
+
+impl Cache {
+    pub fn new(args: &CacheArgs, client_args: &ClientArgs, config_path: &Path) -> Result<Self> {
+        let Some(ttl) = args.cache_ttl.as_ref().filter(|_| !args.no_cache) else {
+            return Ok(Self::default());
+        };
+        let ttl = duration_str::parse(ttl)
+            .with_context(|| format!("cannot parse `{ttl}` as duration"))?;
+        let config_path = shellexpand::path::full(config_path)?;
+        let dir = config_path
+            .parent()
+            .context("config path has no parent dir")?
+            .join("cache");
+        let profile = client_args
+            .profile
+            .clone()
+            .or_else(|| env::var("AWS_PROFILE").ok());
+        let region = client_args
+            .region
+            .clone()
+            .or_else(|| env::var("AWS_REGION").ok());
+        Ok(Self {
+            dir: Some(dir),
+            ttl,
+            refresh: args.refresh,
+            scope: format!("{profile:?} {region:?}"),
+        })
+    }
+
+    /// Returns the cached value if it's fresh
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(key)?;
+        let entry = read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Entry<T>>(&text).ok())
+            .filter(|e| e.key == self.full_key(key))?;
+        let now = time_arg::unix_now().ok()?.as_millis() as i64;
+        if now - entry.created > self.ttl.as_millis() as i64 {
+            debug!("cache entry {path:?} is expired");
+            return None;
+        }
+        debug!("use cache entry {path:?}");
+        Some(entry.value)
+    }
+
+    /// Stores the value, failures are only logged as the cache is optional
+    pub fn put<T: Serialize>(&self, key: &str, value: T) {
+        let Some(path) = self.path(key) else {
+            return;
+        };
+        let result = time_arg::unix_now().and_then(|now| {
+            let entry = Entry {
+                key: self.full_key(key),
+                created: now.as_millis() as i64,
+                value,
+            };
+            create_dir_all(path.parent().expect("cache dir"))?;
+            write(&path, serde_json::to_string(&entry)?)?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            debug!("cannot write cache entry {path:?}: {e:#}");
+        }
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        format!("{} {key}", self.scope)
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        self.full_key(key).hash(&mut hasher);
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{:016x}.json", hasher.finish())),
+        )
+    }
+}
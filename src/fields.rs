@@ -0,0 +1,92 @@
+use itertools::Itertools;
+use serde_json::Value;
+
+/// Returns `path=value` pairs for the fields if the message is a JSON object,
+/// a path is dot separated keys or array indices, ex: `req.headers.0`
+pub fn project(message: &str, fields: &[String]) -> Option<String> {
+    let value = parse_object(message)?;
+    Some(
+        fields
+            .iter()
+            .map(|path| {
+                let field = path
+                    .split('.')
+                    .try_fold(&value, |v, key| match v {
+                        Value::Object(map) => map.get(key),
+                        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                        _ => None,
+                    })
+                    .map(to_string)
+                    .unwrap_or_default();
+                format!("{path}={field}")
+            })
+            .join(" "),
+    )
+}
+
+/// Returns `path=value` pairs for all leaf fields if the message is a JSON object
+pub fn flatten(message: &str) -> Option<String> {
+    let value = parse_object(message)?;
+    let mut pairs = vec![];
+    flatten_into(&value, String::new(), &mut pairs);
+    Some(pairs.join(" "))
+}
+
+fn flatten_into(value: &Value, path: String, pairs: &mut Vec<String>) {
+    let child_path = |key: &dyn std::fmt::Display| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, v) in map {
+                flatten_into(v, child_path(key), pairs);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_into(v, child_path(&i), pairs);
+            }
+        }
+        _ => pairs.push(format!("{path}={}", to_string(value))),
+    }
+}
+
+fn parse_object(message: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(message)
+        .ok()
+        .filter(Value::is_object)
+}
+
+/// Strings without quotes, other values as JSON
+fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_fields() {
+        let message = r#"{"user":{"id":42},"req":{"path":"/a","tags":["x","y"]}}"#;
+        assert_eq!(
+            project(
+                message,
+                &["user.id".into(), "req.tags.1".into(), "no.such".into()]
+            ),
+            Some("user.id=42 req.tags.1=y no.such=".into())
+        );
+        assert_eq!(
+            flatten(message),
+            Some("user.id=42 req.path=/a req.tags.0=x req.tags.1=y".into())
+        );
+        assert_eq!(project("plain text", &["a".into()]), None);
+    }
+}
@@ -1,52 +1,178 @@
-use crate::streams;
+use crate::{
+    cache::Cache,
+    output::{print_line, ListPrinter},
+    streams, time_arg,
+    utils::{arn_region, format_opt_unix_ms, group_identifier, ApiContext, Operation},
+    GroupsArgs, StreamsArgs,
+};
 
 use super::utils::OptFuture;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
-use cloudwatchlogs::operation::describe_log_groups::builders::DescribeLogGroupsInputBuilder;
+use cloudwatchlogs::{
+    operation::describe_log_groups::builders::DescribeLogGroupsInputBuilder, types::LogGroup,
+};
 use humansize::{format_size, DECIMAL};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 pub async fn print(
     client: &cloudwatchlogs::Client,
-    pattern: Option<String>,
-    streams: bool,
-    verbose: bool,
+    cache: &Cache,
+    args: &GroupsArgs,
 ) -> Result<()> {
-    let template = DescribeLogGroupsInputBuilder::default().set_log_group_name_pattern(pattern);
-    let mut groups = vec![];
-    let mut opt_res = Some(template.clone().send_with(client).await);
-    while let Some(res) = opt_res {
-        let output = res.context("describe log groups call failed")?;
-        groups.append(&mut output.log_groups.unwrap());
-        opt_res = output
-            .next_token
-            .map(|t| template.clone().next_token(t).send_with(client))
-            .resolve()
-            .await;
-    }
+    let creation_after = args
+        .creation_after
+        .as_deref()
+        .map(time_arg::parse)
+        .transpose()?;
+    let creation_before = args
+        .creation_before
+        .as_deref()
+        .map(time_arg::parse)
+        .transpose()?;
+    let key = format!("groups {:?}", args.pattern);
+    let mut groups = if let Some(cached) = cache.get::<Vec<CachedGroup>>(&key) {
+        cached.into_iter().map(LogGroup::from).collect()
+    } else {
+        let groups = describe(client, args.pattern.clone()).await?;
+        cache.put(
+            &key,
+            groups.iter().map(CachedGroup::from).collect::<Vec<_>>(),
+        );
+        groups
+    };
+    groups.retain(|g| {
+        let created = g.creation_time.unwrap_or_default();
+        creation_after.is_none_or(|t| created >= t) && creation_before.is_none_or(|t| created < t)
+    });
     groups.sort_by(|l, r| l.log_group_name.cmp(&r.log_group_name));
+    let mut printer = ListPrinter::new(args.format);
     let mut count = 0;
     let mut total_size = 0;
     for g in groups {
+        count += 1;
+        total_size += g.stored_bytes.unwrap_or_default();
+        if args.summary_only {
+            continue;
+        }
         if let Some(name) = g.log_group_name {
-            if !verbose {
-                println!("{name}");
+            let text = if !args.verbose {
+                name.clone()
             } else {
-                println!(
-                    "{name} size {}",
-                    format_size(g.stored_bytes.unwrap_or(0) as u64, DECIMAL)
+                format!(
+                    "{name} size {} created {:?}",
+                    format_size(g.stored_bytes.unwrap_or(0) as u64, DECIMAL),
+                    format_opt_unix_ms(g.creation_time),
                 )
+            };
+            let mut record = json!({
+                "name": name,
+                "stored_bytes": g.stored_bytes,
+                "creation_time": g.creation_time,
+            });
+            let streams_args = StreamsArgs {
+                group: name,
+                verbose: args.verbose,
+                ..Default::default()
+            };
+            if args.streams && !printer.is_text() {
+                record["streams"] = streams::list(client, cache, &streams_args)
+                    .await?
+                    .iter()
+                    .map(streams::record)
+                    .collect();
             }
-            if streams {
-                streams::print(client, name, None, verbose, true).await?;
+            printer.print(&text, record)?;
+            if args.streams && printer.is_text() {
+                streams::print(client, cache, &streams_args, true, None).await?;
             }
         }
-        count += 1;
-        total_size += g.stored_bytes.unwrap_or_default();
     }
-    println!(
+    let total = format!(
         "Total: {count} groups, size: {}",
         format_size(total_size as u64, DECIMAL)
     );
-    Ok(())
+    if args.summary_only {
+        printer.print(&total, json!({"groups": count, "stored_bytes": total_size}))?;
+    } else if printer.is_text() {
+        print_line(total)?;
+    }
+    printer.finish()
+}
+
+/// Returns the group ARN without the `:*` suffix
+pub async fn arn(client: &cloudwatchlogs::Client, group: &str) -> Result<String> {
+    if arn_region(group).is_some() {
+        return Ok(group_identifier(group).to_string());
+    }
+    let template = DescribeLogGroupsInputBuilder::default().log_group_name_prefix(group);
+    let mut opt_res = Some(template.clone().send_with(client).await);
+    while let Some(res) = opt_res {
+        let output = res.api_context(Operation::DescribeLogGroups, None)?;
+        let found = output
+            .log_groups
+            .unwrap_or_default()
+            .into_iter()
+            .find(|g| g.log_group_name.as_deref() == Some(group));
+        if let Some(found) = found {
+            return found
+                .log_group_arn
+                .or_else(|| found.arn.map(|arn| group_identifier(&arn).to_string()))
+                .with_context(|| format!("group `{group}` has no ARN"));
+        }
+        opt_res = output
+            .next_token
+            .map(|t| template.clone().next_token(t).send_with(client))
+            .resolve()
+            .await;
+    }
+    bail!("group `{group}` is not found")
+}
+
+async fn describe(
+    client: &cloudwatchlogs::Client,
+    pattern: Option<String>,
+) -> Result<Vec<LogGroup>> {
+    let template = DescribeLogGroupsInputBuilder::default().set_log_group_name_pattern(pattern);
+    let mut groups = vec![];
+    let mut opt_res = Some(template.clone().send_with(client).await);
+    while let Some(res) = opt_res {
+        let output = res.api_context(Operation::DescribeLogGroups, None)?;
+        groups.append(&mut output.log_groups.unwrap());
+        opt_res = output
+            .next_token
+            .map(|t| template.clone().next_token(t).send_with(client))
+            .resolve()
+            .await;
+    }
+    Ok(groups)
+}
+
+/// Group fields kept in the cache
+#[derive(Serialize, Deserialize)]
+struct CachedGroup {
+    name: Option<String>,
+    stored_bytes: Option<i64>,
+    creation_time: Option<i64>,
+}
+
+impl From<&LogGroup> for CachedGroup {
+    fn from(g: &LogGroup) -> Self {
+        Self {
+            name: g.log_group_name.clone(),
+            stored_bytes: g.stored_bytes,
+            creation_time: g.creation_time,
+        }
+    }
+}
+
+impl From<CachedGroup> for LogGroup {
+    fn from(g: CachedGroup) -> Self {
+        LogGroup::builder()
+            .set_log_group_name(g.name)
+            .set_stored_bytes(g.stored_bytes)
+            .set_creation_time(g.creation_time)
+            .build()
+    }
 }
@@ -0,0 +1,157 @@
+use std::{
+    fs::{read_dir, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use log::debug;
+
+use crate::log::{Completion, EventInfo};
+
+/// Format of local event files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// CloudWatch export to S3, `<timestamp> <message>` lines, gzipped or not
+    #[default]
+    CwExport,
+}
+
+/// Reads events in `[start, end)` from the files and the files in the directories and
+/// passes them to the consumer ordered by timestamp, the stream of an event is the name
+/// of its file directory as exports are saved to `<prefix>/<task id>/<stream>/<n>.gz`
+pub fn read<ConsumerFn>(
+    paths: &[PathBuf],
+    format: InputFormat,
+    start: i64,
+    end: i64,
+    reverse: bool,
+    consumer: &mut ConsumerFn,
+) -> Result<Completion>
+where
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
+{
+    let mut files = vec![];
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+    let mut events = vec![];
+    for file in files {
+        let text = read_text(&file)?;
+        let stream = file
+            .parent()
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().into_owned());
+        let parsed = match format {
+            InputFormat::CwExport => parse_export(&text, start, end),
+        };
+        debug!("{} events in the range in {}", parsed.len(), file.display());
+        events.extend(parsed.into_iter().map(|(t, m)| (t, m, stream.clone())));
+    }
+    // stable, so events with the same timestamp keep the file order
+    events.sort_by_key(|(t, _, _)| *t);
+    if reverse {
+        events.reverse();
+    }
+    for (t, m, stream) in events {
+        let info = EventInfo {
+            stream,
+            event_id: None,
+            ingestion_time: None,
+        };
+        if !consumer(Some(t), Some(m), info) {
+            return Ok(Completion::Stopped);
+        }
+    }
+    Ok(Completion::Complete)
+}
+
+/// Adds the file or the files in the directory and its subdirectories sorted by name
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = read_dir(path)
+        .with_context(|| format!("cannot read input dir {}", path.display()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("cannot read input dir {}", path.display()))?;
+    entries.sort();
+    for entry in entries {
+        collect_files(&entry, files)?;
+    }
+    Ok(())
+}
+
+/// Reads the file, `.gz` files are decompressed
+fn read_text(path: &Path) -> Result<String> {
+    let file =
+        File::open(path).with_context(|| format!("cannot open input file {}", path.display()))?;
+    let mut text = String::new();
+    if path.extension().is_some_and(|e| e == "gz") {
+        MultiGzDecoder::new(file).read_to_string(&mut text)
+    } else {
+        let mut file = file;
+        file.read_to_string(&mut text)
+    }
+    .with_context(|| format!("cannot read input file {}", path.display()))?;
+    Ok(text)
+}
+
+/// Parses `<timestamp> <message>` lines, the timestamp is RFC 3339 or unix time in ms,
+/// lines without it continue the message of the previous line, lines before the first
+/// timestamp are skipped, ex: the `aws-logs-write-test` file of the export
+fn parse_export(text: &str, start: i64, end: i64) -> Vec<(i64, String)> {
+    let mut events: Vec<(i64, String)> = vec![];
+    for line in text.lines() {
+        let event = line
+            .split_once(' ')
+            .and_then(|(t, m)| Some((export_timestamp(t)?, m)));
+        match (event, events.last_mut()) {
+            (Some((t, m)), _) => events.push((t, m.to_string())),
+            (None, Some((_, message))) => {
+                message.push('\n');
+                message.push_str(line);
+            }
+            (None, None) => {}
+        }
+    }
+    events.retain(|(t, _)| (start..end).contains(t));
+    events
+}
+
+fn export_timestamp(value: &str) -> Option<i64> {
+    // 13 digits, so numbers at the start of continuation lines aren't taken as timestamps
+    if value.len() == 13 && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().ok();
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_lines() {
+        let text = "Permission Check Successful\n\
+            2024-01-01T00:00:00.001Z first\n\
+            1704067200002 second\n  at line 1\n42 more\n\
+            1704067200003 \n\
+            2024-01-01T00:00:01.000Z late";
+        assert_eq!(
+            parse_export(text, 1704067200000, 1704067201000),
+            vec![
+                (1704067200001, "first".to_string()),
+                (1704067200002, "second\n  at line 1\n42 more".to_string()),
+                (1704067200003, String::new()),
+            ]
+        );
+    }
+}
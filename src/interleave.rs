@@ -0,0 +1,215 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+
+/// Lines of a local file to merge with CloudWatch events by timestamp
+pub struct FileLines {
+    tag: String,
+    reader: BufReader<File>,
+    /// the last line until its new line is read
+    partial: Vec<u8>,
+    /// timestamp of the last line, the next lines without a timestamp get it
+    last: i64,
+    start: i64,
+    /// None with --follow, lines appended to the file later are read by `until`
+    end: Option<i64>,
+    lines: VecDeque<(i64, String)>,
+    /// the first error of reading appended lines, returned by `finish`
+    error: Option<io::Error>,
+}
+
+impl FileLines {
+    /// Reads lines within `[start, end)`, lines without a leading timestamp
+    /// get the timestamp of the previous line or `start` if there is none,
+    /// without `end` the file is read without blocking, so it can be a FIFO
+    pub fn open(path: &Path, start: i64, end: Option<i64>) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        #[cfg(unix)]
+        if end.is_none() {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+        let file = options
+            .open(path)
+            .with_context(|| format!("cannot open interleave file {}", path.display()))?;
+        let mut lines = Self {
+            tag: path
+                .file_name()
+                .map_or(path.to_string_lossy(), |n| n.to_string_lossy())
+                .into_owned(),
+            reader: BufReader::new(file),
+            partial: vec![],
+            last: start,
+            start,
+            end,
+            lines: VecDeque::new(),
+            error: None,
+        };
+        lines.read_new(end.is_some());
+        match lines.error.take() {
+            Some(e) => {
+                Err(e).with_context(|| format!("cannot read interleave file {}", path.display()))
+            }
+            None => Ok(lines),
+        }
+    }
+
+    /// Source tag of the lines, the file name
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Removes and returns lines with the timestamp up to `timestamp`,
+    /// reads appended lines first with --follow
+    pub fn until(&mut self, timestamp: i64) -> Vec<(i64, String)> {
+        if self.end.is_none() {
+            self.read_new(false);
+        }
+        let count = self.lines.partition_point(|(t, _)| *t <= timestamp);
+        self.lines.drain(..count).collect()
+    }
+
+    /// Removes and returns all remaining lines
+    pub fn rest(&mut self) -> Vec<(i64, String)> {
+        if self.end.is_none() {
+            self.read_new(true);
+        }
+        self.lines.drain(..).collect()
+    }
+
+    /// Returns the error of reading appended lines, should be called after the last line
+    pub fn finish(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e).with_context(|| format!("cannot read interleave file {}", self.tag)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads complete lines up to the end of the file or while a FIFO has data,
+    /// with `complete` the last line without a new line is added at the end of the file
+    fn read_new(&mut self, complete: bool) {
+        if self.error.is_some() {
+            return;
+        }
+        let count = self.lines.len();
+        loop {
+            match self.reader.read_until(b'\n', &mut self.partial) {
+                Ok(0) => {
+                    if complete {
+                        self.push_partial();
+                    }
+                    break;
+                }
+                Ok(_) if self.partial.ends_with(b"\n") => self.push_partial(),
+                // the end of the file in the middle of a line, the next read returns 0
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+        if self.lines.len() != count {
+            // stable, so lines with the same timestamp keep the file order
+            self.lines.make_contiguous().sort_by_key(|(t, _)| *t);
+        }
+    }
+
+    /// Adds the read line if it's within the range
+    fn push_partial(&mut self) {
+        if self.partial.is_empty() {
+            return;
+        }
+        let line = String::from_utf8_lossy(&self.partial);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        self.last = leading_timestamp(line).unwrap_or(self.last);
+        if self.last >= self.start && self.end.is_none_or(|end| self.last < end) {
+            self.lines.push_back((self.last, line.to_string()));
+        }
+        self.partial.clear();
+    }
+}
+
+/// Parses an RFC 3339 or a local `%Y-%m-%d %H:%M:%S` timestamp at the line start as unix ms
+fn leading_timestamp(line: &str) -> Option<i64> {
+    let mut words = line.split_whitespace();
+    let first = words.next()?;
+    if let Ok(t) = DateTime::parse_from_rfc3339(first) {
+        return Some(t.timestamp_millis());
+    }
+    let local = NaiveDateTime::parse_from_str(first, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .or_else(|| {
+            let second = words.next()?;
+            NaiveDateTime::parse_from_str(&format!("{first} {second}"), "%Y-%m-%d %H:%M:%S%.f").ok()
+        })?;
+    local
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.timestamp_millis())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn file_lines() {
+        let dir = std::env::temp_dir().join(format!("cw-axe-interleave-{}", std::process::id()));
+        let path = dir.join("app.log");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            &path,
+            "2024-01-02T03:04:05Z a\n  continued\r\nno timestamp\n2024-01-02T03:04:04Z b",
+        )
+        .unwrap();
+        let t = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+            .unwrap()
+            .timestamp_millis();
+        let mut lines = FileLines::open(&path, t - 1000, Some(t + 1)).unwrap();
+        assert_eq!(lines.tag(), "app.log");
+        assert_eq!(
+            lines.until(t - 1),
+            vec![(t - 1000, "2024-01-02T03:04:04Z b".into())]
+        );
+        assert_eq!(
+            lines.rest(),
+            vec![
+                (t, "2024-01-02T03:04:05Z a".into()),
+                (t, "  continued".into()),
+                (t, "no timestamp".into()),
+            ]
+        );
+
+        // appended lines are read with --follow, a line is taken only after its new line
+        let mut lines = FileLines::open(&path, t - 1000, None).unwrap();
+        assert_eq!(lines.until(t).len(), 3);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b" late\n2024-01-02T03:04:06Z c").unwrap();
+        assert_eq!(
+            lines.until(t + 1000),
+            vec![(t - 1000, "2024-01-02T03:04:04Z b late".into())]
+        );
+        file.write_all(b"\n").unwrap();
+        assert_eq!(
+            lines.until(t + 1000),
+            vec![(t + 1000, "2024-01-02T03:04:06Z c".into())]
+        );
+        assert!(lines.finish().is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(leading_timestamp("2024-01-02 03:04:05.678 x").is_some());
+        assert!(leading_timestamp("03:04:05 x").is_none());
+    }
+}
@@ -0,0 +1,47 @@
+use clap::ValueEnum;
+
+/// log level, ordered by severity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[value(rename_all = "UPPER")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// what to do with lines without a detected level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Unlevelled {
+    Keep,
+    Drop,
+}
+
+/// Returns the first upper case level word found in the message
+pub fn detect(message: &str) -> Option<Level> {
+    message
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find_map(|word| match word {
+            "TRACE" => Some(Level::Trace),
+            "DEBUG" => Some(Level::Debug),
+            "INFO" => Some(Level::Info),
+            "WARN" | "WARNING" => Some(Level::Warn),
+            "ERROR" | "FATAL" | "CRITICAL" => Some(Level::Error),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_level() {
+        assert_eq!(detect("2024-01-02 [INFO] started"), Some(Level::Info));
+        assert_eq!(detect("WARNING: disk is full"), Some(Level::Warn));
+        assert_eq!(detect("level=ERROR msg=\"INFO later\""), Some(Level::Error));
+        assert_eq!(detect("no information here"), None);
+        assert!(Level::Trace < Level::Debug && Level::Warn < Level::Error);
+    }
+}
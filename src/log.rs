@@ -1,20 +1,44 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{stdout, IsTerminal},
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
 
+use crate::fields;
+use crate::input;
+use crate::interleave::FileLines;
+use crate::level::{self, Unlevelled};
+use crate::merge;
+use crate::output::{self, spawn_pager, FlushPolicy, Printer};
+use crate::rotate::{Rotate, RotatingWriter};
+use crate::streams;
+#[cfg(all(unix, feature = "syslog"))]
+use crate::syslog::Forwarder;
+use crate::time_arg;
 #[cfg(feature = "ui")]
 use crate::ui;
-use crate::utils::{local_time, OptFuture};
+use crate::utils::{
+    escape_control, group_identifier, local_time, truncate_chars, ApiContext, Operation, OptFuture,
+    SortWindow,
+};
 use crate::LogArgs;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
-use chrono::{DateTime, Days, Local, NaiveTime};
+
 use clap::{parser::ValueSource, ArgMatches};
+use cloudwatchlogs::error::ProvideErrorMetadata;
 use cloudwatchlogs::operation::{
     filter_log_events::builders::FilterLogEventsInputBuilder,
     get_log_events::builders::GetLogEventsInputBuilder,
 };
+use itertools::{Either, Itertools};
 use log::debug;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+use serde_json::json;
 use toml_edit::DocumentMut;
 
 pub async fn print(
@@ -23,35 +47,416 @@ pub async fn print(
     arg_matches: &ArgMatches,
     config: &DocumentMut,
 ) -> Result<()> {
-    let datetime_format = if arg_matches.value_source("datetime_format")
-        != Some(ValueSource::CommandLine)
-        && config.contains_key("detetime_format")
-    {
-        config.get("datetime_format").unwrap().as_str().unwrap()
+    // command line flags override the group config, which overrides the global one
+    let config_value = |key: &str| {
+        (arg_matches.value_source(key) != Some(ValueSource::CommandLine))
+            .then(|| config_str(config, &args.group, key))
+            .transpose()
+            .map(Option::flatten)
+    };
+    let datetime_format = config_value("datetime_format")?.unwrap_or(&args.datetime_format);
+    let separator = config_value("separator")?.unwrap_or(&args.separator);
+
+    #[cfg(feature = "ui")]
+    if args.ui {
+        let (lines, range, live) = if args.follow {
+            let (live, range) = spawn_live(client.clone(), args.clone(), datetime_format)?;
+            (vec![], range, Some(live))
+        } else {
+            let (lines, range) = query_lines(client, args, datetime_format).await?;
+            if lines.is_empty() {
+                return Ok(());
+            }
+            (lines, range, None)
+        };
+        let filter = args.filter.as_deref().and_then(plain_filter_term);
+        return ui::run(
+            lines,
+            filter.unwrap_or_default().to_string(),
+            spawn_reload(
+                client.clone(),
+                args.clone(),
+                datetime_format.to_string(),
+                range,
+            ),
+            live,
+            ui::SaveFormat {
+                datetime_format: datetime_format.to_string(),
+                separator: separator.to_string(),
+                json_indent: args.json_indent as usize,
+                json_fields: (!args.json_fields.is_empty()).then(|| args.json_fields.clone()),
+            },
+        );
+    }
+
+    let unix_now = time_arg::unix_now()?;
+    let (start, end) = time_range(args, &unix_now)?;
+    #[cfg(all(unix, feature = "syslog"))]
+    if let Some(mut forwarder) = forwarder(args)? {
+        let mut error = None;
+        let result = query(client, args, start, end, |_, m, _| {
+            match forwarder.send(&m) {
+                Ok(()) => true,
+                Err(e) => {
+                    error = Some(e);
+                    false
+                }
+            }
+        })
+        .await;
+        return error.map_or(result, Err);
+    }
+    let tee = args
+        .tee
+        .as_ref()
+        .map(|path| {
+            let rotate = args
+                .output_rotate
+                .as_deref()
+                .map(Rotate::parse)
+                .transpose()?;
+            RotatingWriter::new(path, rotate)
+        })
+        .transpose()?;
+    // --compact drops the date if all events are from today, it's known before the query
+    // only if the whole range is today, otherwise events are buffered to check them,
+    // with --follow the range decides
+    let now = unix_now.as_millis() as i64;
+    let range_format = compact_datetime_format(start, end, now);
+    let mut buffered = None;
+    let datetime_format = if !args.compact {
+        datetime_format
+    } else if args.follow || range_format == TIME_FORMAT {
+        range_format
+    } else {
+        let mut events = vec![];
+        let result = query(client, args, start, end, |t, m, info| {
+            events.push((t, m, info));
+            true
+        })
+        .await;
+        let (first, last) = events
+            .iter()
+            .filter_map(|(t, _, _)| *t)
+            .minmax()
+            .into_option()
+            .unwrap_or((start, end));
+        buffered = Some((result, events));
+        compact_datetime_format(first, last, now)
+    };
+    let pager = (!args.no_pager && stdout().is_terminal())
+        .then(spawn_pager)
+        .flatten();
+    let raw_delimiter = args
+        .raw_bytes
+        .then(|| output::parse_delimiter(&args.raw_delimiter))
+        .transpose()?;
+    let mut printer = Printer::new(args.output, datetime_format, args.json_indent as usize, tee)
+        .with_pager(pager)
+        .with_stream(args.stream.clone().filter(|_| args.streams.is_empty()))
+        .with_csv_columns(args.csv_columns.clone())
+        .with_json_fields((!args.json_fields.is_empty()).then(|| args.json_fields.clone()))
+        .with_epoch(args.both_times.then_some(args.epoch_unit))
+        .with_relative(args.relative_timestamps)
+        .with_event_id(args.show_event_id)
+        .with_separator(separator)
+        .with_color(args.color.enabled())
+        .with_flush(FlushPolicy {
+            buffer_size: args.output_buffer_size,
+            every: args.flush_every,
+            interval: args
+                .flush_interval
+                .as_deref()
+                .map(|v| {
+                    duration_str::parse(v)
+                        .with_context(|| format!("cannot parse `{v}` as duration"))
+                })
+                .transpose()?,
+        })
+        .with_raw_delimiter(raw_delimiter);
+    let result = if let Some((result, events)) = buffered {
+        for (t, m, info) in events {
+            if !printer.print(t, &m, &info) {
+                break;
+            }
+        }
+        result
+    } else {
+        query(client, args, start, end, |t, m, info| {
+            printer.print(t, &m, &info)
+        })
+        .await
+    };
+    printer.finish()?;
+    result
+}
+
+#[cfg(all(unix, feature = "syslog"))]
+fn forwarder(args: &LogArgs) -> Result<Option<Forwarder>> {
+    #[cfg(target_os = "linux")]
+    if args.to_journald {
+        return Forwarder::journald(args.syslog_facility, args.syslog_severity).map(Some);
+    }
+    if args.to_syslog {
+        return Forwarder::syslog(args.syslog_facility, args.syslog_severity).map(Some);
+    }
+    Ok(None)
+}
+
+/// Returns the string value of the key from the `[group."<group>"]` config table
+/// or the global one if the group table doesn't have it
+fn config_str<'a>(config: &'a DocumentMut, group: &str, key: &str) -> Result<Option<&'a str>> {
+    let item = config
+        .get("group")
+        .and_then(|groups| groups.get(group))
+        .and_then(|table| table.get(key))
+        .or_else(|| config.get(key));
+    item.map(|item| {
+        item.as_str()
+            .with_context(|| format!("config value of `{key}` is not a string"))
+    })
+    .transpose()
+}
+
+/// Fetches all events for the UI, returns them with the resolved range
+#[cfg(feature = "ui")]
+async fn query_lines(
+    client: &cloudwatchlogs::Client,
+    args: &LogArgs,
+    datetime_format: &str,
+) -> Result<(ui::Lines, ui::Range)> {
+    let unix_now = time_arg::unix_now()?;
+    let (start, end) = time_range(args, &unix_now)?;
+    let mut lines = vec![];
+    query(client, args, start, end, |t, m, info| {
+        lines.push(ui::Line {
+            timestamp: t,
+            datetime: String::new(),
+            message: m,
+            stream: info.stream,
+        });
+        true
+    })
+    .await?;
+    let datetime_format = if args.compact {
+        let (first, last) = lines
+            .iter()
+            .filter_map(|line| line.timestamp)
+            .minmax()
+            .into_option()
+            .unwrap_or((start, end));
+        compact_datetime_format(first, last, unix_now.as_millis() as i64)
     } else {
-        &args.datetime_format
+        datetime_format
     };
+    for line in &mut lines {
+        line.datetime = format!(
+            "{}",
+            local_time(line.timestamp.unwrap_or(0)).format(datetime_format)
+        );
+    }
+    Ok((lines, (start, end)))
+}
+
+/// Starts a task which sends lines of the --follow query to the UI as they arrive
+#[cfg(feature = "ui")]
+fn spawn_live(
+    client: cloudwatchlogs::Client,
+    args: LogArgs,
+    datetime_format: &str,
+) -> Result<(ui::Live, ui::Range)> {
+    let unix_now = time_arg::unix_now()?;
+    let (start, end) = time_range(&args, &unix_now)?;
+    let datetime_format = if args.compact {
+        compact_datetime_format(start, end, unix_now.as_millis() as i64)
+    } else {
+        datetime_format
+    }
+    .to_string();
+    let (sender, live) = std::sync::mpsc::channel();
+    tokio::spawn(async move {
+        let result = query(&client, &args, start, end, |t, m, info| {
+            let line = ui::Line {
+                timestamp: t,
+                datetime: format!("{}", local_time(t.unwrap_or(0)).format(&datetime_format)),
+                message: m,
+                stream: info.stream,
+            };
+            sender.send(Ok(line)).is_ok()
+        })
+        .await;
+        if let Err(e) = result {
+            let _ = sender.send(Err(format!("{e:#}")));
+        }
+    });
+    Ok((live, (start, end)))
+}
+
+/// Starts a task which runs queries requested by the UI with the changed args
+#[cfg(feature = "ui")]
+fn spawn_reload(
+    client: cloudwatchlogs::Client,
+    args: LogArgs,
+    datetime_format: String,
+    range: ui::Range,
+) -> ui::Reload {
+    let (requests, mut request_receiver) = tokio::sync::mpsc::unbounded_channel::<ui::Query>();
+    let (result_sender, results) = std::sync::mpsc::channel();
+    let query = ui::Query {
+        start: args.start.clone(),
+        end: args.end.clone().unwrap_or_default(),
+        filter: args.filter.clone().unwrap_or_default(),
+    };
+    tokio::spawn(async move {
+        while let Some(query) = request_receiver.recv().await {
+            debug!("reload {query:?}");
+            let mut args = args.clone();
+            args.start = query.start;
+            args.since_file_mtime = None;
+            if !query.end.is_empty() {
+                args.end = Some(query.end);
+                args.length = None;
+            } else {
+                args.end = None;
+            }
+            args.filter = Some(query.filter).filter(|f| !f.is_empty());
+            let result = query_lines(&client, &args, &datetime_format)
+                .await
+                .map_err(|e| format!("{e:#}"));
+            if result_sender.send(result).is_err() {
+                break;
+            }
+        }
+    });
+    ui::Reload {
+        query,
+        range,
+        requests,
+        results,
+    }
+}
+
+/// Source tag of CloudWatch events when they are merged with --interleave-file
+const CLOUDWATCH_TAG: &str = "cloudwatch";
+
+/// Stop reason of --limit
+const LIMIT_REACHED: &str = "limit reached";
 
-    let unix_now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .context("cannot get unix time as duration")?;
-    let start = parse_offset_or_duration(&args.start, &unix_now)?;
+/// Minimal interval between `--notify-on` bells
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Resolves start and end as unix time in ms
+fn time_range(args: &LogArgs, unix_now: &Duration) -> Result<(i64, i64)> {
+    if args.end.as_ref().is_some_and(|end| end.starts_with('-')) {
+        bail!("--end can't be before --start, use `+<duration>` or --length");
+    }
+    // `+<duration>` is the same as --length
+    let end_offset = args.end.as_deref().and_then(|end| end.strip_prefix('+'));
+    // None if the end is relative to the start
+    let end = match (&args.end, &args.length) {
+        (Some(_), _) if end_offset.is_some() => None,
+        (Some(end), _) => Some(time_arg::parse_relative_to(end, unix_now)?),
+        (None, Some(_)) => None,
+        (None, None) => Some(unix_now.as_millis() as i64),
+    };
+    let start = if let Some(path) = &args.since_file_mtime {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("cannot get modification time of {}", path.display()))?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .context("modification time is before unix epoch")?
+            .as_millis() as i64
+    } else if let Some(offset) = args.start.strip_prefix('-') {
+        let end =
+            end.context("--start relative to --end can't be used with --length or `--end +`")?;
+        end - duration_str::parse(offset)
+            .with_context(|| format!("cannot parse `{offset}` as duration"))?
+            .as_millis() as i64
+    } else {
+        time_arg::parse_relative_to(&args.start, unix_now)?
+    };
     // TODO: add check for end and length at the same time
-    let end = if let Some(end) = &args.end {
-        parse_offset_or_duration(end, &unix_now)?
-    } else if let Some(length) = &args.length {
+    let end = if let Some(end) = end {
+        end
+    } else {
+        let length = end_offset
+            .or(args.length.as_deref())
+            .expect("end is relative only with an offset or length");
         start
             + duration_str::parse(length)
                 .with_context(|| format!("cannot parse `{length}` as duration"))?
                 .as_millis() as i64
-    } else {
-        unix_now.as_millis() as i64
     };
+    if let Some(to) = args.align_to {
+        let start = time_arg::align(local_time(start), to, false).timestamp_millis();
+        let end = time_arg::align(local_time(end), to, true).timestamp_millis();
+        return Ok((start, end));
+    }
+    Ok((start, end))
+}
 
+/// Fetches events, filters and replaces messages on the client side and passes them
+/// with the event info to `emit`
+async fn query<EmitFn>(
+    client: &cloudwatchlogs::Client,
+    args: &LogArgs,
+    start: i64,
+    end: i64,
+    mut emit: EmitFn,
+) -> Result<()>
+where
+    EmitFn: FnMut(Option<i64>, String, EventInfo) -> bool,
+{
     let message_regexp = args
         .message_regexp
+        .iter()
+        .map(|v| RegexWithReplace::new(v))
+        .collect::<Result<Vec<_>>>()?;
+    let grep_i = args
+        .grep_i
+        .iter()
+        .map(|v| {
+            RegexBuilder::new(v)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("failed to parse {v} as regex"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let grep = regexes(&args.grep)?;
+    let grep_invert = regexes(&args.grep_invert)?;
+    let until_match = args
+        .until_match
+        .as_ref()
+        .map(|v| Regex::new(v).with_context(|| format!("failed to parse {v} as regex")))
+        .transpose()?;
+    let notify_on = args
+        .notify_on
+        .as_ref()
+        .map(|v| Regex::new(v).with_context(|| format!("failed to parse {v} as regex")))
+        .transpose()?;
+    let mut drop_before_first_match = args
+        .drop_before_first_match
+        .as_ref()
+        .map(|v| Regex::new(v).with_context(|| format!("failed to parse {v} as regex")))
+        .transpose()?;
+    let count_by = args
+        .count_by
         .as_ref()
-        .map(|v| RegexWithReplace::new(v.as_str()).unwrap());
+        .map(|v| {
+            let re = Regex::new(v).with_context(|| format!("failed to parse {v} as regex"))?;
+            if re.captures_len() < 2 {
+                bail!("--count-by regex `{v}` has no capture group");
+            }
+            Ok(re)
+        })
+        .transpose()?;
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let mut last_notification: Option<Instant> = None;
+    let timeout = args
+        .timeout
+        .as_ref()
+        .map(|v| duration_str::parse(v).with_context(|| format!("cannot parse `{v}` as duration")))
+        .transpose()?;
 
     debug!(
         "query\n from: {start} {}\n to:   {end} {}",
@@ -59,84 +464,509 @@ pub async fn print(
         local_time(end)
     );
 
-    #[cfg(feature = "ui")]
-    let mut lines = vec![];
-    let mut consumer = |t: Option<i64>, m: Option<String>| {
-        let m = if let Some(re) = &message_regexp {
-            re.re
-                .replace(&m.unwrap_or_default(), re.replacement)
-                .to_string()
+    let mut stop_reason = None;
+    let mut emitted = 0;
+    let mut consumer = |t: Option<i64>, m: Option<String>, tag: Option<&str>, info: EventInfo| {
+        let m = m.unwrap_or_default();
+        if args.skip_empty && m.is_empty() {
+            return true;
+        }
+        let m = match &args.strip_timestamp_prefix {
+            Some(Some(format)) => time_arg::strip_time_prefix(&m, &[format]).to_string(),
+            Some(None) => {
+                time_arg::strip_time_prefix(&m, &time_arg::COMMON_PREFIX_FORMATS).to_string()
+            }
+            None => m,
+        };
+        if let Some(min_level) = args.min_level {
+            let keep =
+                level::detect(&m).map_or(args.unlevelled == Unlevelled::Keep, |l| l >= min_level);
+            if !keep {
+                return true;
+            }
+        }
+        let m = if !args.fields.is_empty() {
+            fields::project(&m, &args.fields).unwrap_or(m)
+        } else if args.flatten_json {
+            fields::flatten(&m).unwrap_or(m)
         } else {
-            m.unwrap_or_default()
+            m
         };
+        let m = replace_all_rules(&message_regexp, m);
+        if args.regexp_drop_empty && m.is_empty() {
+            return true;
+        }
+        if !grep_i.iter().chain(&grep).all(|re| re.is_match(&m))
+            || grep_invert.iter().any(|re| re.is_match(&m))
+        {
+            return true;
+        }
+        let m = if args.escape_control {
+            escape_control(&m)
+        } else {
+            m
+        };
+        let m = if let Some(max) = args.max_message_length {
+            truncate_chars(m, max)
+        } else {
+            m
+        };
+        let m = if let Some(tag) = tag {
+            format!("[{tag}] {m}")
+        } else {
+            m
+        };
+        if drop_before_first_match
+            .as_ref()
+            .is_some_and(|re| !re.is_match(&m))
+        {
+            return true;
+        }
+        drop_before_first_match = None;
+        if notify_on.as_ref().is_some_and(|re| re.is_match(&m))
+            && last_notification.is_none_or(|n| n.elapsed() >= NOTIFY_INTERVAL)
+        {
+            last_notification = Some(Instant::now());
+            eprint!("\x07");
+        }
+        let matched = until_match.as_ref().is_some_and(|re| re.is_match(&m));
+        if let Some(key) = count_by
+            .as_ref()
+            .and_then(|re| re.captures(&m))
+            .and_then(|c| c.get(1))
+        {
+            *counts.entry(key.as_str().to_string()).or_default() += 1;
+        }
+        if !emit(t, m, info) {
+            stop_reason = Some("output failed");
+            return false;
+        }
+        emitted += 1;
+        if matched {
+            stop_reason = Some("until match found");
+        } else if args.limit.is_some_and(|limit| emitted >= limit) {
+            stop_reason = Some(LIMIT_REACHED);
+            return false;
+        }
+        !matched
+    };
 
-        #[cfg(feature = "ui")]
-        if args.ui {
-            lines.push((
-                format!("{}", local_time(t.unwrap_or(0)).format(datetime_format)),
-                m,
-            ))
+    let mut sort_window = args
+        .local_sort_window
+        .as_ref()
+        .map(|w| {
+            duration_str::parse(w)
+                .with_context(|| format!("cannot parse `{w}` as duration"))
+                .map(SortWindow::new)
+        })
+        .transpose()?;
+    let mut file_lines = args
+        .interleave_file
+        .as_deref()
+        .map(|path| FileLines::open(path, start, (!args.follow).then_some(end)))
+        .transpose()?;
+    // passes file lines up to the event timestamp before the event, tagged with the source
+    let mut merged_consumer = |t: Option<i64>, m: Option<String>, info: EventInfo| {
+        if let Some(file_lines) = &mut file_lines {
+            for (line_t, line) in file_lines.until(t.unwrap_or_default()) {
+                if !consumer(
+                    Some(line_t),
+                    Some(line),
+                    Some(file_lines.tag()),
+                    EventInfo::default(),
+                ) {
+                    return false;
+                }
+            }
+            consumer(t, m, Some(CLOUDWATCH_TAG), info)
+        } else {
+            consumer(t, m, None, info)
+        }
+    };
+    let mut window_consumer = |t: Option<i64>, m: Option<String>, info: EventInfo| {
+        let t = match (&args.tz_from_message, &m) {
+            (Some(format), Some(m)) => time_arg::parse_message_time(m, format).or(t),
+            _ => t,
+        };
+        if let Some(window) = &mut sort_window {
+            window
+                .push(t, (m, info))
+                .into_iter()
+                .all(|(t, (m, info))| merged_consumer(t, m, info))
         } else {
-            print_event(&t, &m, datetime_format)
+            merged_consumer(t, m, info)
         }
-        #[cfg(not(feature = "ui"))]
-        print_event(&t, &m, datetime_format)
     };
 
-    if let Some(filter) = &args.filter {
-        print_filter_events(client, args, start, end, filter, &mut consumer).await
+    let streams = if let Some(limit) = args.limit_streams {
+        streams::most_recent(client, &args.group, limit as usize).await?
     } else {
-        print_all_events(client, args, start, end, &mut consumer).await
-    }?;
-
-    #[cfg(feature = "ui")]
-    if args.ui && !lines.is_empty() {
-        ui::run(lines)
+        stream_names(args)?
+    };
+    let poll_interval = args
+        .follow
+        .then(|| {
+            duration_str::parse(&args.poll_interval)
+                .with_context(|| format!("cannot parse `{}` as duration", args.poll_interval))
+        })
+        .transpose()?;
+    let fetch = async {
+        // only polls repeat events
+        let mut seen = poll_interval.map(|_| SeenEvents::default());
+        let (mut from, mut to) = (start, end);
+        loop {
+            let completion = fetch_range(client, args, from, to, &streams, &mut |t, m, info| {
+                if let Some(seen) = &mut seen {
+                    if !seen.insert(t, info.stream.as_deref(), m.as_deref().unwrap_or_default()) {
+                        return true;
+                    }
+                }
+                window_consumer(t, m, info)
+            })
+            .await?;
+            let Some(interval) =
+                poll_interval.filter(|_| !matches!(completion, Completion::Stopped))
+            else {
+                return Ok::<_, anyhow::Error>(completion);
+            };
+            tokio::time::sleep(interval).await;
+            from = seen
+                .as_mut()
+                .and_then(SeenEvents::next_poll)
+                .unwrap_or(from);
+            to = time_arg::unix_now()?.as_millis() as i64;
+            debug!("poll from {} to {}", local_time(from), local_time(to));
+        }
+    };
+    let completion = if let Some(timeout) = timeout {
+        tokio::time::timeout(timeout, fetch)
+            .await
+            .with_context(|| format!("timed out after {timeout:?}"))??
     } else {
-        Ok(())
+        fetch.await?
+    };
+    if !matches!(completion, Completion::Stopped) {
+        let drained = sort_window
+            .map(SortWindow::drain)
+            .unwrap_or_default()
+            .into_iter()
+            .all(|(t, (m, info))| merged_consumer(t, m, info));
+        if let (true, Some(file_lines)) = (drained, &mut file_lines) {
+            for (t, line) in file_lines.rest() {
+                if !consumer(
+                    Some(t),
+                    Some(line),
+                    Some(file_lines.tag()),
+                    EventInfo::default(),
+                ) {
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(file_lines) = &mut file_lines {
+        file_lines.finish()?;
+    }
+    if args.report {
+        if let Some(reason) = stop_reason {
+            eprintln!("results truncated (reason: {reason})");
+        } else {
+            eprintln!("{completion}");
+        }
+    }
+    if count_by.is_some() {
+        for (key, count) in sorted_counts(counts) {
+            eprintln!("{count}\t{key}");
+        }
+    }
+    if let (Some(re), None | Some(LIMIT_REACHED)) = (&until_match, stop_reason) {
+        bail!("no event matched `{re}`");
     }
-    #[cfg(not(feature = "ui"))]
     Ok(())
 }
 
+fn regexes(values: &[String]) -> Result<Vec<Regex>> {
+    values
+        .iter()
+        .map(|v| Regex::new(v).with_context(|| format!("failed to parse {v} as regex")))
+        .collect()
+}
+
+/// Returns --count-by counts, the most frequent first
+fn sorted_counts(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(lk, lc), (rk, rc)| rc.cmp(lc).then_with(|| lk.cmp(rk)));
+    counts
+}
+
+/// Fetches events in the range with the API matching the stream selection
+async fn fetch_range<ConsumerFn>(
+    client: &cloudwatchlogs::Client,
+    args: &LogArgs,
+    start: i64,
+    end: i64,
+    streams: &[String],
+    consumer: &mut ConsumerFn,
+) -> Result<Completion>
+where
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
+{
+    if !args.input.is_empty() {
+        let format = args.input_format;
+        return input::read(&args.input, format, start, end, args.reverse, consumer);
+    }
+    let single = streams.len() == 1 && args.filter.is_none() && args.stream_name_prefix.is_none();
+    // only GetLogEvents of a single stream can return the newest events first
+    let newest_first = single && args.reverse;
+    let mut past_end = false;
+    let completion = if single {
+        let mut consumer = before_end(end, newest_first, &mut past_end, consumer);
+        print_all_events(client, args, start, end, &streams[0], &mut consumer).await?
+    } else if !args.reverse {
+        let mut consumer = before_end(end, false, &mut past_end, consumer);
+        fetch_oldest_first(client, args, start, end, streams, &mut consumer).await?
+    } else {
+        let mut events = vec![];
+        let completion = {
+            let mut push = |t, m, info| {
+                events.push((t, m, info));
+                true
+            };
+            let mut push = before_end(end, false, &mut past_end, &mut push);
+            fetch_oldest_first(client, args, start, end, streams, &mut push).await?
+        };
+        for (t, m, info) in events.into_iter().rev() {
+            if !consumer(t, m, info) {
+                return Ok(Completion::Stopped);
+            }
+        }
+        completion
+    };
+    if past_end && !newest_first && matches!(completion, Completion::Stopped) {
+        debug!("stopped at the first event past the end");
+        return Ok(Completion::Complete);
+    }
+    Ok(completion)
+}
+
+/// Passes events before the end to the consumer, the API may still return late events
+/// after it, ex: eventually consistent pages, oldest first events stop at the first one,
+/// newest first ones are skipped, `past_end` is set if any event was past the end
+fn before_end<'a, ConsumerFn>(
+    end: i64,
+    newest_first: bool,
+    past_end: &'a mut bool,
+    consumer: &'a mut ConsumerFn,
+) -> impl FnMut(Option<i64>, Option<String>, EventInfo) -> bool + 'a
+where
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
+{
+    move |t, m, s| {
+        if t.is_some_and(|t| t >= end) {
+            *past_end = true;
+            return newest_first;
+        }
+        consumer(t, m, s)
+    }
+}
+
+async fn fetch_oldest_first<ConsumerFn>(
+    client: &cloudwatchlogs::Client,
+    args: &LogArgs,
+    start: i64,
+    end: i64,
+    streams: &[String],
+    consumer: &mut ConsumerFn,
+) -> Result<Completion>
+where
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
+{
+    if streams.is_empty() && args.filter.is_none() && args.stream_name_prefix.is_none() {
+        merge::print_merged_events(client, args, start, end, consumer).await
+    } else {
+        print_filter_events(client, args, start, end, streams, consumer).await
+    }
+}
+
+/// Events with the latest timestamp, a --follow poll or a query restarted after a token
+/// expiration starts from the timestamp, so these events are returned again and have to be
+/// skipped, only hashes of their streams and messages are kept
+#[derive(Default)]
+struct SeenEvents {
+    last: Option<i64>,
+    keys: Vec<u64>,
+    /// keys at `last` from the previous polls, not returned yet by the current one
+    pending: Vec<u64>,
+}
+
+impl SeenEvents {
+    /// Returns false if the event was returned by a previous poll
+    fn insert(&mut self, timestamp: Option<i64>, stream: Option<&str>, message: &str) -> bool {
+        let Some(t) = timestamp else {
+            return true;
+        };
+        let mut hasher = DefaultHasher::new();
+        (stream, message).hash(&mut hasher);
+        let key = hasher.finish();
+        match self.last {
+            Some(last) if t < last => {}
+            Some(last) if t == last => {
+                if let Some(i) = self.pending.iter().position(|k| *k == key) {
+                    self.pending.swap_remove(i);
+                    return false;
+                }
+                self.keys.push(key);
+            }
+            _ => {
+                self.last = Some(t);
+                self.keys = vec![key];
+                self.pending.clear();
+            }
+        }
+        true
+    }
+
+    /// Returns the start of the next poll
+    fn next_poll(&mut self) -> Option<i64> {
+        self.pending = self.keys.clone();
+        self.last
+    }
+}
+
+/// Stream and ID of an event, only FilterLogEvents returns IDs
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventInfo {
+    pub stream: Option<String>,
+    pub event_id: Option<String>,
+    /// unix time in ms when CloudWatch received the event
+    pub ingestion_time: Option<i64>,
+}
+
+/// How pagination ended
+pub enum Completion {
+    /// all events in the range are returned
+    Complete,
+    /// FilterLogEvents returned an empty page with a next token, more events may be available
+    EmptyPage,
+    /// the consumer asked to stop
+    Stopped,
+}
+
+impl Display for Completion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Completion::Complete => write!(f, "results complete"),
+            Completion::EmptyPage => write!(f, "results truncated (reason: empty page)"),
+            Completion::Stopped => write!(f, "results truncated (reason: stopped)"),
+        }
+    }
+}
+
 async fn print_all_events<ConsumerFn>(
     client: &cloudwatchlogs::Client,
     args: &LogArgs,
     start: i64,
     end: i64,
+    stream: &str,
     consumer: &mut ConsumerFn,
-) -> Result<()>
+) -> Result<Completion>
 where
-    ConsumerFn: FnMut(Option<i64>, Option<String>),
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
 {
-    let template = GetLogEventsInputBuilder::default()
-        .log_group_name(&args.group)
-        .log_stream_name(&args.stream)
+    let mut template = GetLogEventsInputBuilder::default()
+        .log_group_identifier(group_identifier(&args.group))
+        .log_stream_name(stream)
         .limit(args.chunk_size as i32)
-        .start_from_head(true)
+        .start_from_head(!args.reverse)
         .start_time(start)
         .end_time(end);
 
-    let mut opt_res = Some(template.clone().send_with(client).await);
+    let mut progress = args.progress_to.as_deref().map(Progress::new);
+    // an expired token of the next page is retried from the last event, not the cursor,
+    // only forward queries are restarted
+    let mut seen = (!args.reverse).then(SeenEvents::default);
+    let mut paged = false;
+    let mut request_start = Instant::now();
+    let mut opt_res = Some(
+        template
+            .clone()
+            .set_next_token(args.cursor.clone())
+            .send_with(client)
+            .await,
+    );
     while let Some(res) = opt_res {
-        let output = res.context("get log events failed")?;
+        let output = match res {
+            Err(e) if paged && !args.reverse && token_expired(&e) => {
+                let from = seen
+                    .as_mut()
+                    .and_then(SeenEvents::next_poll)
+                    .unwrap_or(start);
+                debug!(
+                    "get log events token expired, restart from {}",
+                    local_time(from)
+                );
+                template = template.start_time(from);
+                paged = false;
+                request_start = Instant::now();
+                opt_res = Some(template.clone().send_with(client).await);
+                continue;
+            }
+            res => res.api_context(Operation::GetLogEvents, Some(&args.group))?,
+        };
+        let latency = request_start.elapsed();
         if let Some(events) = output.events {
             if events.is_empty() {
+                debug!("get log events page: 0 events in {latency:?}");
                 break;
             }
-            for event in events.into_iter() {
-                consumer(event.timestamp, event.message);
+            let count = events.len();
+            let processing_start = Instant::now();
+            let last_timestamp = events.last().and_then(|e| e.timestamp);
+            // events of a page are oldest first in both directions
+            let events = if args.reverse {
+                Either::Left(events.into_iter().rev())
+            } else {
+                Either::Right(events.into_iter())
+            };
+            for event in events {
+                let message = event.message.as_deref().unwrap_or_default();
+                if let Some(seen) = &mut seen {
+                    if !seen.insert(event.timestamp, Some(stream), message) {
+                        continue;
+                    }
+                }
+                let info = EventInfo {
+                    stream: Some(stream.to_string()),
+                    event_id: None,
+                    ingestion_time: event.ingestion_time,
+                };
+                if !consumer(event.timestamp, event.message, info) {
+                    return Ok(Completion::Stopped);
+                }
+            }
+            debug!(
+                "get log events page: {count} events in {latency:?}, processed in {:?}",
+                processing_start.elapsed()
+            );
+            if let Some(progress) = &mut progress {
+                progress.update(output.next_forward_token.as_deref(), last_timestamp)?;
             }
         } else {
             break;
         }
-        opt_res = output
-            .next_forward_token
+        paged = true;
+        request_start = Instant::now();
+        let next_token = if args.reverse {
+            output.next_backward_token
+        } else {
+            output.next_forward_token
+        };
+        opt_res = next_token
             .map(|t| template.clone().next_token(t).send_with(client))
             .resolve()
             .await;
     }
-    Ok(())
+    Ok(Completion::Complete)
 }
 
 async fn print_filter_events<ConsumerFn>(
@@ -144,153 +974,570 @@ async fn print_filter_events<ConsumerFn>(
     args: &LogArgs,
     start: i64,
     end: i64,
-    filter: &str,
+    streams: &[String],
     consumer: &mut ConsumerFn,
-) -> Result<()>
+) -> Result<Completion>
 where
-    ConsumerFn: FnMut(Option<i64>, Option<String>),
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
 {
-    let template = FilterLogEventsInputBuilder::default()
-        .log_group_name(&args.group)
-        .log_stream_names(&args.stream)
+    let mut template = FilterLogEventsInputBuilder::default()
+        .log_group_identifier(group_identifier(&args.group))
+        .set_log_stream_names((!streams.is_empty()).then(|| streams.to_vec()))
+        .set_log_stream_name_prefix(args.stream_name_prefix.clone())
         .limit(args.chunk_size as i32)
         .start_time(start)
         .end_time(end)
-        .filter_pattern(filter);
+        .set_filter_pattern(args.filter.clone());
+    // pages of a multi-stream query are not ordered relative to each other
+    let merge_sort =
+        args.merge_sort_across_pages || args.stream_name_prefix.is_some() || streams.len() > 1;
 
-    let mut opt_res = Some(template.clone().send_with(client).await);
+    let mut completion = Completion::Complete;
+    let mut buffer = vec![];
+    let mut progress = args.progress_to.as_deref().map(Progress::new);
+    // an expired token of the next page is retried from the last event, not the cursor,
+    // buffered events aren't consumed between pages, so their tokens don't expire
+    let mut seen = (!merge_sort).then(SeenEvents::default);
+    let mut paged = false;
+    let mut request_start = Instant::now();
+    let mut opt_res = Some(
+        template
+            .clone()
+            .set_next_token(args.cursor.clone())
+            .send_with(client)
+            .await,
+    );
     while let Some(res) = opt_res {
-        let output = res.context("filter log events failed")?;
-        if let Some(events) = output.events {
+        let output = match res {
+            Err(e) if paged && !merge_sort && token_expired(&e) => {
+                let from = seen
+                    .as_mut()
+                    .and_then(SeenEvents::next_poll)
+                    .unwrap_or(start);
+                debug!(
+                    "filter log events token expired, restart from {}",
+                    local_time(from)
+                );
+                template = template.start_time(from);
+                paged = false;
+                request_start = Instant::now();
+                opt_res = Some(template.clone().send_with(client).await);
+                continue;
+            }
+            res => res.api_context(Operation::FilterLogEvents, Some(&args.group))?,
+        };
+        let latency = request_start.elapsed();
+        if let Some(mut events) = output.events {
             if events.is_empty() {
+                debug!("filter log events page: 0 events in {latency:?}");
+                if output.next_token.is_some() {
+                    completion = Completion::EmptyPage;
+                }
                 break;
             }
-            for event in events.into_iter() {
-                consumer(event.timestamp, event.message);
+            let count = events.len();
+            let processing_start = Instant::now();
+            let last_timestamp = events.last().and_then(|e| e.timestamp);
+            if merge_sort {
+                buffer.append(&mut events);
+            } else {
+                for event in events.into_iter() {
+                    let message = event.message.as_deref().unwrap_or_default();
+                    let stream = event.log_stream_name.as_deref();
+                    if let Some(seen) = &mut seen {
+                        if !seen.insert(event.timestamp, stream, message) {
+                            continue;
+                        }
+                    }
+                    let info = EventInfo {
+                        stream: event.log_stream_name,
+                        event_id: event.event_id,
+                        ingestion_time: event.ingestion_time,
+                    };
+                    if !consumer(event.timestamp, event.message, info) {
+                        return Ok(Completion::Stopped);
+                    }
+                }
+            }
+            debug!(
+                "filter log events page: {count} events in {latency:?}, processed in {:?}",
+                processing_start.elapsed()
+            );
+            if let Some(progress) = &mut progress {
+                progress.update(output.next_token.as_deref(), last_timestamp)?;
             }
         } else {
             break;
         }
+        paged = true;
+        request_start = Instant::now();
         opt_res = output
             .next_token
             .map(|t| template.clone().next_token(t).send_with(client))
             .resolve()
             .await;
     }
-    Ok(())
+    buffer
+        .sort_by(|l, r| (l.timestamp, &l.log_stream_name).cmp(&(r.timestamp, &r.log_stream_name)));
+    for event in buffer.into_iter() {
+        let info = EventInfo {
+            stream: event.log_stream_name,
+            event_id: event.event_id,
+            ingestion_time: event.ingestion_time,
+        };
+        if !consumer(event.timestamp, event.message, info) {
+            return Ok(Completion::Stopped);
+        }
+    }
+    Ok(completion)
 }
 
-fn print_event(timestamp: &Option<i64>, message: &str, datetime_format: &str) {
-    let datetime = local_time(timestamp.unwrap_or(0)).format(datetime_format);
-    println!("{datetime}|{}", message)
+/// Returns true if the request failed because the page token expired,
+/// ex: the output was blocked by the pager for too long
+fn token_expired(error: &impl ProvideErrorMetadata) -> bool {
+    error.code() == Some("InvalidParameterException")
+        && error
+            .message()
+            .is_some_and(|m| m.to_lowercase().contains("token"))
 }
 
-fn parse_offset_or_duration(value: &str, unix_now: &Duration) -> Result<i64> {
-    parse_as_epoch_ms(value)
-        .or_else(|_| {
-            duration_str::parse(value).map(|o| unix_now.saturating_sub(o).as_millis() as i64)
-        })
-        .or_else(|_| {
-            NaiveTime::parse_from_str(value, "%H:%M")
-                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
-                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S.%3f"))
-                .map_err(|_| 0)
-                .and_then(|n| {
-                    DateTime::from_timestamp_millis(unix_now.as_millis() as i64)
-                        .unwrap()
-                        .with_timezone(&Local)
-                        .with_time(n)
-                        .single()
-                        .map(|v| {
-                            if v.timestamp_millis() > (unix_now.as_millis() as i64) {
-                                v.checked_sub_days(Days::new(1)).unwrap().timestamp_millis()
-                            } else {
-                                v.timestamp_millis()
-                            }
-                        })
-                        .ok_or(0)
-                })
-        })
-        .or_else(|_| {
-            NaiveTime::parse_from_str(value, "%H:%MZ")
-                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%SZ"))
-                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S.%3fZ"))
-                .map_err(|_| 0)
-                .and_then(|n| {
-                    DateTime::from_timestamp_millis(unix_now.as_millis() as i64)
-                        .unwrap()
-                        .with_time(n)
-                        .single()
-                        .map(|v| {
-                            if v.timestamp_millis() > (unix_now.as_millis() as i64) {
-                                v.checked_sub_days(Days::new(1)).unwrap().timestamp_millis()
-                            } else {
-                                v.timestamp_millis()
-                            }
-                        })
-                        .ok_or(0)
-                })
-        })
-        .or_else(|_| DateTime::parse_from_rfc3339(value).map(|d| d.timestamp_millis()))
-        .with_context(|| {
-            format!("failed to parse `{value}` as duration, time, UTC time or RFC3339")
-        })
+/// Minimal interval between --progress-to writes
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Saves the pagination position to resume with --cursor,
+/// the last position is also saved when it's dropped
+struct Progress<'a> {
+    path: &'a Path,
+    last_write: Option<Instant>,
+    next_token: Option<String>,
+    last_timestamp: Option<i64>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            last_write: None,
+            next_token: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Remembers the position of a processed page and saves it if the interval passed
+    fn update(&mut self, next_token: Option<&str>, last_timestamp: Option<i64>) -> Result<()> {
+        self.next_token = next_token.map(str::to_string);
+        self.last_timestamp = last_timestamp;
+        if self
+            .last_write
+            .is_some_and(|t| t.elapsed() < PROGRESS_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Writes via a temporary file, so the file is never partially written
+    fn save(&mut self) -> Result<()> {
+        let progress = json!({
+            "next_token": self.next_token,
+            "last_timestamp": self.last_timestamp,
+        });
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, progress.to_string())
+            .and_then(|_| std::fs::rename(&tmp, self.path))
+            .with_context(|| format!("cannot write progress to {}", self.path.display()))?;
+        self.last_write = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl Drop for Progress<'_> {
+    fn drop(&mut self) {
+        if self.last_write.is_some() {
+            if let Err(e) = self.save() {
+                debug!("{e:#}");
+            }
+        }
+    }
+}
+
+/// Returns the stream to query, the deduplicated streams from `--streams-file`
+/// or nothing to query all streams
+fn stream_names(args: &LogArgs) -> Result<Vec<String>> {
+    if let Some(path) = &args.streams_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read streams file {}", path.display()))?;
+        parse_stream_list(&text).with_context(|| format!("invalid streams file {}", path.display()))
+    } else {
+        let mut streams: Vec<String> = vec![];
+        for name in args.stream.iter().chain(&args.streams) {
+            if !streams.contains(name) {
+                streams.push(name.clone());
+            }
+        }
+        Ok(streams)
+    }
+}
+
+/// Parses newline-delimited stream names, keeping the first occurrence of duplicates
+fn parse_stream_list(text: &str) -> Result<Vec<String>> {
+    let mut streams: Vec<String> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let name = line.trim();
+        if name.is_empty() {
+            bail!("stream name at line {} is blank", i + 1);
+        }
+        if !streams.iter().any(|s| s == name) {
+            streams.push(name.to_string());
+        }
+    }
+    if streams.is_empty() {
+        bail!("no stream names found");
+    }
+    // FilterLogEvents accepts at most 100 stream names
+    if streams.len() > 100 {
+        bail!(
+            "too many streams: {}, at most 100 are supported",
+            streams.len()
+        );
+    }
+    Ok(streams)
+}
+
+/// Returns the term if the filter is a single term or an exact phrase,
+/// so it can be used as a plain substring match
+#[cfg(feature = "ui")]
+fn plain_filter_term(filter: &str) -> Option<&str> {
+    let term = filter
+        .strip_prefix('"')
+        .and_then(|f| f.strip_suffix('"'))
+        .filter(|f| !f.contains('"'))
+        .unwrap_or(filter);
+    if term.is_empty()
+        || (term.len() == filter.len() && filter.contains(|c: char| " ?-%{}[]*\"'".contains(c)))
+    {
+        None
+    } else {
+        Some(term)
+    }
 }
 
-fn parse_as_epoch_ms(candidate: &str) -> anyhow::Result<i64> {
-    let ms = candidate.parse::<i64>()?;
-    if ms > 946684800000 {
-        // 2000-01-01 in ms
-        Ok(ms)
+/// --compact format without the date
+const TIME_FORMAT: &str = "%H:%M:%S";
+
+/// Skips the date when the first and the last events or the range bounds are today,
+/// so all events between them are today as well
+fn compact_datetime_format(first: i64, last: i64, now: i64) -> &'static str {
+    let today = local_time(now).date_naive();
+    if local_time(first).date_naive() == today && local_time(last).date_naive() == today {
+        TIME_FORMAT
     } else {
-        Ok(ms * 1000)
+        "%d%b %H:%M:%S"
     }
 }
 
 struct RegexWithReplace<'a> {
     re: Regex,
     replacement: &'a str,
+    /// replace all matches instead of the first one
+    global: bool,
 }
 
 impl<'a> RegexWithReplace<'a> {
     fn new(s: &'a str) -> Result<Self> {
-        let delimiter = s.chars().next().unwrap();
-        let p = s
-            .strip_prefix(delimiter)
-            .unwrap()
+        let malformed = || format!("`{s}` must be `<delimiter><regexp><delimiter><replacement>`");
+        let delimiter = s.chars().next().with_context(malformed)?;
+        let p = s[delimiter.len_utf8()..]
             .split_once(delimiter)
-            .unwrap();
+            .with_context(malformed)?;
+        // only `g` after the last delimiter is a flag, other endings stay in the replacement
+        let (replacement, global) = match p.1.rsplit_once(delimiter) {
+            Some((replacement, "g")) => (replacement, true),
+            _ => (p.1, false),
+        };
         Ok(Self {
             re: Regex::new(p.0).with_context(|| format!("failed to parse {} as regex", p.0))?,
-            replacement: p.1,
+            replacement,
+            global,
         })
     }
+
+    /// Replaces the first or all matches, it can't fail for a valid regex: the message and
+    /// the replacement are UTF-8 and missing groups are replaced with an empty string
+    fn replace(&self, message: &str) -> String {
+        if self.global {
+            self.re.replace_all(message, self.replacement).into_owned()
+        } else {
+            self.re.replace(message, self.replacement).into_owned()
+        }
+    }
+}
+
+/// Applies the rules in order, each one to the result of the previous one
+fn replace_all_rules(rules: &[RegexWithReplace], message: String) -> String {
+    rules.iter().fold(message, |m, re| re.replace(&m))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use chrono::{DateTime, Days, NaiveTime};
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        args: LogArgs,
+    }
 
+    fn log_args(args: &[&str]) -> LogArgs {
+        TestCli::parse_from(["axe", "group", "stream"].iter().chain(args)).args
+    }
+
+    #[cfg(feature = "ui")]
     #[test]
-    fn offset_or_duration() {
-        let ts = Duration::from_secs(
-            DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z")
+    fn plain_filter() {
+        assert_eq!(plain_filter_term("ERROR"), Some("ERROR"));
+        assert_eq!(plain_filter_term("\"A B\""), Some("A B"));
+        assert_eq!(plain_filter_term("A B"), None);
+        assert_eq!(plain_filter_term("?A"), None);
+        assert_eq!(plain_filter_term("{ $.level = 1 }"), None);
+    }
+
+    #[test]
+    fn compact_format() {
+        let now = DateTime::parse_from_rfc3339("2024-01-02T12:00:00Z")
+            .unwrap()
+            .timestamp_millis();
+        let midnight = local_time(now)
+            .with_time(NaiveTime::MIN)
+            .single()
+            .unwrap()
+            .timestamp_millis();
+        assert_eq!(compact_datetime_format(midnight, now, now), "%H:%M:%S");
+        assert_eq!(
+            compact_datetime_format(midnight - 1, now, now),
+            "%d%b %H:%M:%S"
+        );
+    }
+
+    #[test]
+    fn seen_events() {
+        let mut seen = SeenEvents::default();
+        assert!(seen.insert(Some(2), None, "a"));
+        assert!(seen.insert(Some(1), None, "b"));
+        assert!(seen.insert(Some(2), None, "c"));
+        assert!(seen.insert(Some(2), Some("s"), "c"));
+        assert_eq!(seen.next_poll(), Some(2));
+        assert!(!seen.insert(Some(2), None, "c"));
+        assert!(seen.insert(Some(2), None, "d"));
+        assert!(!seen.insert(Some(2), None, "a"));
+        assert!(seen.insert(Some(2), None, "a"));
+        assert!(!seen.insert(Some(2), Some("s"), "c"));
+        assert!(seen.insert(Some(3), None, "c"));
+        assert_eq!(seen.next_poll(), Some(3));
+    }
+
+    #[test]
+    fn group_config() {
+        let config: DocumentMut = r#"
+            datetime_format = "%H:%M"
+            separator = 1
+            [group."/aws/lambda/foo"]
+            datetime_format = "%M"
+        "#
+        .parse()
+        .unwrap();
+        let value = |group, key| config_str(&config, group, key).ok().flatten();
+        assert_eq!(value("/aws/lambda/foo", "datetime_format"), Some("%M"));
+        assert_eq!(value("bar", "datetime_format"), Some("%H:%M"));
+        assert!(config_str(&config, "bar", "separator").is_err());
+        assert_eq!(value("bar", "missing"), None);
+    }
+
+    #[test]
+    fn expired_token() {
+        use cloudwatchlogs::{
+            error::ErrorMetadata, operation::filter_log_events::FilterLogEventsError,
+            types::error::InvalidParameterException,
+        };
+        let error = |message: &str| {
+            FilterLogEventsError::InvalidParameterException(
+                InvalidParameterException::builder()
+                    .message(message)
+                    .meta(
+                        ErrorMetadata::builder()
+                            .code("InvalidParameterException")
+                            .message(message)
+                            .build(),
+                    )
+                    .build(),
+            )
+        };
+        assert!(token_expired(&error("The specified nextToken has expired")));
+        assert!(!token_expired(&error("Invalid limit")));
+        assert!(!token_expired(&FilterLogEventsError::unhandled(
+            "connection reset"
+        )));
+    }
+
+    #[test]
+    fn count_order() {
+        let counts = HashMap::from([("b".into(), 1), ("c".into(), 2), ("a".into(), 1)]);
+        assert_eq!(
+            sorted_counts(counts),
+            vec![("c".into(), 2), ("a".into(), 1), ("b".into(), 1)]
+        );
+    }
+
+    #[test]
+    fn events_past_end() {
+        let events = [(Some(1), "a"), (Some(2), "b"), (None, "c"), (Some(1), "d")];
+        // oldest first stops at the first late event, newest first skips it
+        for (newest_first, expected) in [(false, vec!["a"]), (true, vec!["a", "c", "d"])] {
+            let mut printed = vec![];
+            let mut push = |_, m: Option<String>, _| {
+                printed.push(m.unwrap());
+                true
+            };
+            let mut past_end = false;
+            let all = {
+                let mut consumer = before_end(2, newest_first, &mut past_end, &mut push);
+                events
+                    .iter()
+                    .all(|(t, m)| consumer(*t, Some(m.to_string()), EventInfo::default()))
+            };
+            assert_eq!(all, newest_first);
+            assert!(past_end);
+            assert_eq!(printed, expected);
+        }
+    }
+
+    #[test]
+    fn stream_list() {
+        assert_eq!(parse_stream_list("a\n b \na\n").unwrap(), vec!["a", "b"]);
+        assert!(parse_stream_list("").is_err());
+        assert!(parse_stream_list("a\n\nb").is_err());
+        let args = log_args(&["--stream", "b", "--stream", "stream"]);
+        assert_eq!(stream_names(&args).unwrap(), vec!["stream", "b"]);
+    }
+
+    #[test]
+    fn start_relative_to_end() {
+        let now = Duration::from_secs(1700000000);
+        let end = 1690000000000;
+        let args = log_args(&["--end", "1690000000", "--start", "-30m"]);
+        assert_eq!(time_range(&args, &now).unwrap(), (end - 1800000, end));
+
+        // without --end it's relative to now
+        let args = log_args(&["-s", "-1h"]);
+        let now_ms = now.as_millis() as i64;
+        assert_eq!(time_range(&args, &now).unwrap(), (now_ms - 3600000, now_ms));
+
+        // the end can't depend on the start
+        let args = log_args(&["--start", "-30m", "--length", "10m"]);
+        assert!(time_range(&args, &now).is_err());
+        let args = log_args(&["--start", "-30m", "--end=-10m"]);
+        assert!(time_range(&args, &now).is_err());
+        let args = log_args(&["--start", "-30m", "--end", "+10m"]);
+        assert!(time_range(&args, &now).is_err());
+    }
+
+    #[test]
+    fn end_relative_to_start() {
+        let now = Duration::from_secs(1700000000);
+        let start = 1690000000000;
+        let args = log_args(&["--start", "1690000000", "--end", "+30m"]);
+        assert_eq!(time_range(&args, &now).unwrap(), (start, start + 1800000));
+        let args = log_args(&["--start", "1690000000", "--end", "+x"]);
+        assert!(time_range(&args, &now).is_err());
+    }
+
+    #[test]
+    fn length_from_bare_time_start() {
+        let today = local_time(
+            DateTime::parse_from_rfc3339("2024-01-02T12:00:00Z")
                 .unwrap()
-                .timestamp() as u64,
+                .timestamp_millis(),
         );
-        // TODO: write proper test, maybe change local time zone or just copy implementation logic
-        // TODO: cover other cases
-        assert!(parse_offset_or_duration("10:23", &ts).is_ok());
-        assert!(parse_offset_or_duration("10:23:45", &ts).is_ok());
-        assert!(parse_offset_or_duration("10:23:45.678", &ts).is_ok());
+        let at = |days_ago, h, m| {
+            today
+                .checked_sub_days(Days::new(days_ago))
+                .unwrap()
+                .with_time(NaiveTime::from_hms_opt(h, m, 0).unwrap())
+                .single()
+                .unwrap()
+                .timestamp_millis()
+        };
+        let args = log_args(&["--start", "09:00", "--length", "2h"]);
 
+        // before 09:00 the start is yesterday, the end has to be on the same day
+        let now = Duration::from_millis(at(0, 8, 0) as u64);
         assert_eq!(
-            parse_offset_or_duration("1700000000", &ts).unwrap(),
-            1700000000000
+            time_range(&args, &now).unwrap(),
+            (at(1, 9, 0), at(1, 11, 0))
         );
+
+        let now = Duration::from_millis(at(0, 10, 0) as u64);
         assert_eq!(
-            parse_offset_or_duration("1700000000000", &ts).unwrap(),
-            1700000000000
+            time_range(&args, &now).unwrap(),
+            (at(0, 9, 0), at(0, 11, 0))
         );
     }
+
+    #[test]
+    fn regexp_replace_chain() {
+        let args = log_args(&["-r", "/a+/b", "-r", "#b(.)#$1$1"]);
+        let rules = args
+            .message_regexp
+            .iter()
+            .map(|v| RegexWithReplace::new(v))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(replace_all_rules(&rules, "xaac".to_string()), "xcc");
+        assert_eq!(replace_all_rules(&[], "xaac".to_string()), "xaac");
+    }
+
+    #[test]
+    fn regexp_replace_edge_cases() {
+        let re = RegexWithReplace::new("/(é+)(x)?/[$1$2$9]").unwrap();
+        assert_eq!(re.replace("aéé\u{0}b"), "a[éé]\u{0}b");
+        assert_eq!(re.replace(""), "");
+        let re = RegexWithReplace::new("#.#").unwrap();
+        assert_eq!(re.replace("\u{1F600}a"), "a");
+        let re = RegexWithReplace::new("éaéé").unwrap();
+        assert_eq!(re.replace("bab"), "béb");
+    }
+
+    #[test]
+    fn regexp_replace_named_groups() {
+        let re = RegexWithReplace::new(r"/(?P<ts>\d+) (?P<lvl>\w+)/${lvl} ${ts}").unwrap();
+        // only the first match is replaced
+        assert_eq!(re.replace("1 INFO a 2 WARN b"), "INFO 1 a 2 WARN b");
+        // `$lvlx` is a missing group
+        let re = RegexWithReplace::new(r"/(?P<lvl>\w+)/$lvlx").unwrap();
+        assert_eq!(re.replace("INFO"), "");
+    }
+
+    #[test]
+    fn regexp_replace_global() {
+        let re = RegexWithReplace::new(r"/(?P<ts>\d+) (?P<lvl>\w+)/${lvl} ${ts}/g").unwrap();
+        assert_eq!(re.replace("1 INFO a 2 WARN b"), "INFO 1 a WARN 2 b");
+        let re = RegexWithReplace::new("#[0-9a-f]{8}#<id>#g").unwrap();
+        assert_eq!(re.replace("0123abcd deadbeef"), "<id> <id>");
+        // other endings are a part of the replacement
+        let re = RegexWithReplace::new("/a/b/i").unwrap();
+        assert_eq!(re.replace("aa"), "b/ia");
+        let re = RegexWithReplace::new("/a/b/").unwrap();
+        assert_eq!(re.replace("aa"), "b/a");
+    }
+
+    #[test]
+    fn regexp_replace_malformed() {
+        for value in ["", "/", "/abc", "foo"] {
+            let e = RegexWithReplace::new(value).err().unwrap();
+            assert!(e.to_string().contains("<delimiter>"), "{value}: {e}");
+        }
+        assert!(RegexWithReplace::new("/(/x").is_err());
+    }
 }
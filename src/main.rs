@@ -1,64 +1,154 @@
 use std::{
     env,
     fs::{create_dir_all, write, File},
-    io::{ErrorKind, Read},
+    io::{ErrorKind, Read, Write},
     iter,
     path::PathBuf,
 };
 
 use ::log::debug;
 use anyhow::{Context, Result};
+use aws_config::retry::RetryConfig;
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
-use clap::{parser::ValueSource, Args, Parser, Subcommand};
+use cache::Cache;
+use clap::{builder::ArgPredicate, parser::ValueSource, Args, Parser, Subcommand, ValueEnum};
 use itertools::Itertools;
+use utils::{arn_region, shell_quote};
 
+mod cache;
+mod fields;
 mod groups;
+mod input;
+mod interleave;
+mod level;
 mod log;
+mod merge;
+mod output;
+mod rotate;
 mod streams;
+#[cfg(all(unix, feature = "syslog"))]
+mod syslog;
+mod time_arg;
 #[cfg(feature = "ui")]
 mod ui;
 mod utils;
 
 #[::tokio::main]
 async fn main() -> Result<()> {
+    match run().await {
+        Err(e) if e.is::<output::OutputClosed>() => {
+            debug!("{e}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+async fn run() -> Result<()> {
     let mut args = Cli::parse();
     let mut arg_matches = <Cli as clap::CommandFactory>::command().get_matches();
-    env_logger::Builder::from_env(env_logger::Env::default()).init();
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::default());
+    if args.self_log_json {
+        logger.format(|buf, record| {
+            let record = serde_json::json!({
+                "timestamp": buf.timestamp_millis().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{record}")
+        });
+    }
+    logger.init();
+    // the config path is taken from --config-path, then AXE_CONFIG, then the default path,
+    // an explicitly set path has to exist
     let mut config = read_config(
         &args,
-        arg_matches.value_source("config_path") == Some(ValueSource::CommandLine),
+        matches!(
+            arg_matches.value_source("config_path"),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ),
     )?;
 
+    let mut default_alias = config.get("default").and_then(|v| v.as_str());
     loop {
         let Cli {
-            profile, command, ..
+            client: client_args,
+            command,
+            ..
         } = args;
+        // without a subcommand run the `default` alias from the config once, otherwise show help
+        let command = match (command, default_alias.take()) {
+            (Some(command), _) => command,
+            (None, Some(alias)) => {
+                debug!("run the default alias `{alias}`");
+                Commands::External(vec![alias.to_string()])
+            }
+            (None, None) => {
+                eprint!("{}", <Cli as clap::CommandFactory>::command().render_help());
+                std::process::exit(2);
+            }
+        };
         match command {
-            Commands::Groups {
-                verbose,
-                pattern,
-                streams,
-            } => {
-                return groups::print(&create_client(&profile).await, pattern, streams, verbose)
+            Commands::Groups(groups_args) => {
+                return groups::print(
+                    &create_client(&client_args, None).await?,
+                    &Cache::new(&groups_args.cache, &client_args, &args.config_path)?,
+                    &groups_args,
+                )
+                .await;
+            }
+            Commands::Streams(streams_args) => {
+                let client = create_client(&client_args, Some(&streams_args.group)).await?;
+                let cache = Cache::new(&streams_args.cache, &client_args, &args.config_path)?;
+                let mut log_command = log_command(&client_args, &streams_args.group);
+                if !streams_args.pick {
+                    return streams::print(
+                        &client,
+                        &cache,
+                        &streams_args,
+                        false,
+                        streams_args
+                            .then_log
+                            .then(|| log_command.iter().map(|s| shell_quote(s)).join(" ")),
+                    )
                     .await;
+                }
+                let stream = streams::pick(&client, &cache, &streams_args).await?;
+                log_command.insert(1, "-c".into());
+                log_command.insert(2, args.config_path.to_string_lossy().into_owned());
+                log_command.push(stream);
+                debug!("picked stream, run {log_command:?}");
+                arg_matches =
+                    <Cli as clap::CommandFactory>::command().get_matches_from(&log_command);
+                args = Cli::try_parse_from(&log_command).context("failed to parse log args")?;
             }
-            Commands::Streams {
+            Commands::Recent {
                 group,
-                verbose,
-                prefix,
+                within,
+                filter,
+                log_args,
             } => {
-                return streams::print(
-                    &create_client(&profile).await,
-                    group,
-                    prefix,
-                    verbose,
-                    false,
-                )
-                .await;
+                let mut log_command = log_command(&client_args, &group);
+                log_command.insert(1, "-c".into());
+                log_command.insert(2, args.config_path.to_string_lossy().into_owned());
+                log_command.extend(["--start".into(), within]);
+                if let Some(filter) = filter {
+                    log_command.extend(["--filter".into(), filter]);
+                }
+                log_command.extend(log_args);
+                debug!("recent events, run {log_command:?}");
+                arg_matches =
+                    <Cli as clap::CommandFactory>::command().get_matches_from(&log_command);
+                args = Cli::try_parse_from(&log_command).context("failed to parse log args")?;
+            }
+            Commands::Arn { group } => {
+                let client = create_client(&client_args, Some(&group)).await?;
+                return output::print_line(groups::arn(&client, &group).await?);
             }
             Commands::Log(ref log_args) => {
                 return log::print(
-                    &create_client(&profile).await,
+                    &create_client(&client_args, Some(&log_args.group)).await?,
                     log_args,
                     arg_matches.subcommand().unwrap().1,
                     &config,
@@ -101,26 +191,36 @@ async fn main() -> Result<()> {
                 return Ok(());
             }
             Commands::Aliases => {
-                for (key, value) in config
-                    .get_key_value_mut("alias")
-                    .and_then(|i| i.1.as_table())
-                    .map(|t| t.get_values())
-                    .into_iter()
-                    .flatten()
-                {
-                    println!(
-                        "{}\t\"{}\"",
-                        key[0].get(),
-                        value
-                            .as_array()
-                            .expect("array as value for alias")
-                            .iter()
-                            .map(|v| v.as_str().unwrap())
-                            .join("\" \"")
-                    );
+                for line in alias_lines(&config)? {
+                    output::print_line(line)?;
                 }
                 return Ok(());
             }
+            Commands::Info => {
+                let sdk_config = load_sdk_config(&client_args, None).await?;
+                output::print_line(format!("version: {}", env!("CARGO_PKG_VERSION")))?;
+                output::print_line(format!("target: {}-{}", env::consts::ARCH, env::consts::OS))?;
+                output::print_line(format!("ui: {}", cfg!(feature = "ui")))?;
+                output::print_line(format!(
+                    "config: {}",
+                    shellexpand::path::full(&args.config_path.as_path())?.display()
+                ))?;
+                output::print_line(format!(
+                    "profile: {}",
+                    client_args
+                        .profile
+                        .or_else(|| env::var("AWS_PROFILE").ok())
+                        .unwrap_or("default".into())
+                ))?;
+                output::print_line(format!(
+                    "region: {}",
+                    sdk_config
+                        .region()
+                        .map(|r| r.to_string())
+                        .unwrap_or("not set".into())
+                ))?;
+                return Ok(());
+            }
             Commands::External(v) => {
                 let app_name = env::args().next().unwrap_or("axe".into());
                 let replacement = config
@@ -129,14 +229,12 @@ async fn main() -> Result<()> {
                     .and_then(|t| t.get(v[0].as_str()))
                     .and_then(|i| i.as_array())
                     .with_context(|| format!("no alias found for `{}`", v[0]))?;
+                let replacement =
+                    alias_args(replacement).with_context(|| format!("invalid alias `{}`", v[0]))?;
 
                 let build_iter = || {
                     iter::once(app_name.as_str())
-                        .chain(
-                            replacement
-                                .iter()
-                                .map(|v| v.as_str().expect("only are strings supported in alias")),
-                        )
+                        .chain(replacement.iter().map(|s| s.as_str()))
                         .chain(v[1..].iter().map(|s| s.as_str()))
                 };
                 let new_cli = Cli::try_parse_from(build_iter())
@@ -151,6 +249,32 @@ async fn main() -> Result<()> {
     }
 }
 
+/// Returns the args of the log command for the group with all client args
+fn log_command(client_args: &ClientArgs, group: &str) -> Vec<String> {
+    let app_name = env::args().next().unwrap_or("axe".into());
+    let mut log_command = vec![app_name];
+    if let Some(profile) = &client_args.profile {
+        log_command.extend(["-p".into(), profile.clone()]);
+    }
+    if let Some(region) = &client_args.region {
+        log_command.extend(["--region".into(), region.clone()]);
+    }
+    if let Some(app_name) = &client_args.app_name {
+        log_command.extend(["--app-name".into(), app_name.clone()]);
+    }
+    if client_args.fips {
+        log_command.push("--fips".into());
+    }
+    if let Some(max_attempts) = client_args.max_attempts {
+        log_command.extend(["--max-attempts".into(), max_attempts.to_string()]);
+    }
+    if let Some(retry_mode) = client_args.retry_mode.and_then(|m| m.to_possible_value()) {
+        log_command.extend(["--retry-mode".into(), retry_mode.get_name().to_string()]);
+    }
+    log_command.extend(["log".into(), group.to_string()]);
+    log_command
+}
+
 fn read_config(args: &Cli, fail_on_not_found: bool) -> Result<toml_edit::DocumentMut> {
     debug!(
         "Try to read config from `{:?}`, fail on not found: {fail_on_not_found}",
@@ -160,10 +284,10 @@ fn read_config(args: &Cli, fail_on_not_found: bool) -> Result<toml_edit::Documen
         Ok(mut f) => {
             let mut buf = vec![];
             f.read_to_end(&mut buf).context("cannot read config file")?;
-            let config = String::from_utf8(buf)
-                .context("read config at utf-8 failed")?
+            let text = String::from_utf8(buf).context("read config at utf-8 failed")?;
+            let config = text
                 .parse::<toml_edit::DocumentMut>()
-                .context("config parse failed")?;
+                .map_err(|e| config_parse_error(&text, &args.config_path, &e))?;
             debug!("config:\n{config}");
             Ok(config)
         }
@@ -176,56 +300,201 @@ fn read_config(args: &Cli, fail_on_not_found: bool) -> Result<toml_edit::Documen
     }
 }
 
-async fn create_client(profile: &Option<String>) -> cloudwatchlogs::Client {
+/// Alias arguments, numbers, booleans and dates are converted to strings
+fn alias_args(alias: &toml_edit::Array) -> Result<Vec<String>> {
+    alias
+        .iter()
+        .map(|v| match v {
+            toml_edit::Value::String(s) => Ok(s.value().clone()),
+            toml_edit::Value::Integer(i) => Ok(i.value().to_string()),
+            toml_edit::Value::Float(f) => Ok(f.value().to_string()),
+            toml_edit::Value::Boolean(b) => Ok(b.value().to_string()),
+            toml_edit::Value::Datetime(d) => Ok(d.value().to_string()),
+            toml_edit::Value::Array(_) | toml_edit::Value::InlineTable(_) => {
+                anyhow::bail!("only scalar values are supported in alias, found `{v}`")
+            }
+        })
+        .collect()
+}
+
+/// Returns `<name>\t"<arg>" "<arg>"` lines of the config aliases
+fn alias_lines(config: &toml_edit::DocumentMut) -> Result<Vec<String>> {
+    config
+        .get("alias")
+        .and_then(|i| i.as_table())
+        .map(|t| t.get_values())
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| {
+            let name = key[0].get();
+            let args = value
+                .as_array()
+                .with_context(|| format!("alias `{name}` is not an array"))
+                .and_then(alias_args)
+                .with_context(|| format!("invalid alias `{name}`"))?;
+            Ok(format!("{name}\t\"{}\"", args.join("\" \"")))
+        })
+        .collect()
+}
+
+/// Error with the position and the line where parsing failed
+fn config_parse_error(
+    text: &str,
+    path: &std::path::Path,
+    error: &toml_edit::TomlError,
+) -> anyhow::Error {
+    let Some(span) = error.span() else {
+        return anyhow::format_err!("config parse failed at {path:?}: {}", error.message());
+    };
+    let before = &text[..span.start.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    let snippet = text[line_start..].lines().next().unwrap_or_default();
+    anyhow::format_err!(
+        "config parse failed at {}:{line}:{column}: {}\n{line:>4} | {snippet}\n     | {}^",
+        path.display(),
+        error.message().trim(),
+        " ".repeat(column - 1)
+    )
+}
+
+/// `group` is used to take the region from if it's an ARN and the region isn't set
+async fn create_client(
+    client_args: &ClientArgs,
+    group: Option<&str>,
+) -> Result<cloudwatchlogs::Client> {
+    Ok(aws_sdk_cloudwatchlogs::Client::new(
+        &load_sdk_config(client_args, group).await?,
+    ))
+}
+
+async fn load_sdk_config(
+    client_args: &ClientArgs,
+    group: Option<&str>,
+) -> Result<aws_config::SdkConfig> {
     let mut loader = aws_config::from_env();
-    if let Some(profile) = profile.as_ref() {
+    if let Some(profile) = client_args.profile.as_ref() {
         debug!("Use {profile} profile");
         loader = loader.profile_name(profile);
     }
-    let config = loader.load().await;
-    aws_sdk_cloudwatchlogs::Client::new(&config)
+    if let Some(region) = client_args.region.as_deref().or(group.and_then(arn_region)) {
+        debug!("Use {region} region");
+        loader = loader.region(aws_config::Region::new(region.to_string()));
+    }
+    if let Some(app_name) = client_args.app_name.as_ref() {
+        debug!("Use {app_name} app name");
+        loader = loader.app_name(
+            aws_config::AppName::new(app_name.clone())
+                .with_context(|| format!("invalid app name `{app_name}`"))?,
+        );
+    }
+    if client_args.max_attempts.is_some() || client_args.retry_mode.is_some() {
+        let retry_config = match client_args.retry_mode {
+            Some(RetryMode::Adaptive) => RetryConfig::adaptive(),
+            Some(RetryMode::Standard) | None => RetryConfig::standard(),
+        };
+        let retry_config = match client_args.max_attempts {
+            Some(max_attempts) => retry_config.with_max_attempts(max_attempts),
+            None => retry_config,
+        };
+        debug!("Use {retry_config:?}");
+        loader = loader.retry_config(retry_config);
+    }
+    if client_args.fips {
+        debug!("Use FIPS endpoints");
+        loader = loader.use_fips(true);
+    }
+    Ok(loader.load().await)
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about = "AWS CloudWatch log viewer", long_about = None)]
 struct Cli {
-    /// AWS profile name
-    #[arg(short, long)]
-    profile: Option<String>,
+    #[command(flatten)]
+    client: ClientArgs,
     /// config
-    #[arg(short, long, default_value_os_t = PathBuf::from("~/.config/axe/axe.toml"))]
+    #[arg(short, long, env = "AXE_CONFIG", default_value_os_t = PathBuf::from("~/.config/axe/axe.toml"))]
     config_path: PathBuf,
+    /// write own logs enabled by RUST_LOG to stderr as JSON lines
+    /// with timestamp, level, target and message
+    #[arg(long)]
+    self_log_json: bool,
 
+    /// the `default` alias from the config is used if it's not set
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+}
+
+#[derive(Args, Debug)]
+struct ClientArgs {
+    /// AWS profile name
+    #[arg(short, long)]
+    profile: Option<String>,
+    /// AWS region, by default the region of the group ARN if it's used or the profile one
+    #[arg(long)]
+    region: Option<String>,
+    /// application name sent to AWS as a part of User-Agent
+    #[arg(long, env = "AXE_APP_NAME")]
+    app_name: Option<String>,
+    /// use FIPS endpoints
+    #[arg(long)]
+    fips: bool,
+    /// maximum number of attempts for an AWS request, including the first one
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: Option<u32>,
+    /// AWS request retry mode
+    #[arg(long, value_enum)]
+    retry_mode: Option<RetryMode>,
+}
+
+/// Listing cache options
+#[derive(Args, Clone, Debug, Default)]
+struct CacheArgs {
+    /// reuse listings cached within the duration, ex: 10m, the cache is disabled by default
+    #[arg(long)]
+    cache_ttl: Option<String>,
+    /// don't use the cache even if --cache-ttl is set, ex: in an alias
+    #[arg(long, conflicts_with = "refresh")]
+    no_cache: bool,
+    /// ignore cached listings and update them
+    #[arg(long, requires = "cache_ttl")]
+    refresh: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum RetryMode {
+    Standard,
+    Adaptive,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// show logs
-    Log(LogArgs),
+    Log(Box<LogArgs>),
     /// show log groups
-    Groups {
-        /// verbose (with size)
-        #[arg(short, long)]
-        verbose: bool,
-        /// filter by pattern https://docs.rs/aws-sdk-cloudwatchlogs/latest/aws_sdk_cloudwatchlogs/operation/describe_log_groups/struct.DescribeLogGroupsInput.html#structfield.log_group_name_pattern
-        #[arg(short, long, default_value = None)]
-        pattern: Option<String>,
-        /// show streams
-        #[arg(short, long)]
-        streams: bool,
-    },
+    Groups(GroupsArgs),
     /// show log group streams
-    Streams {
+    Streams(StreamsArgs),
+    /// show recent events of all streams of a group, a shortcut for
+    /// `log <group> --start <within> --filter <filter>`
+    Recent {
         /// group name
         group: String,
-        /// verbose (with details)
+        /// how far back to look, format is the same as for log start
+        #[arg(short, long, default_value = "15m")]
+        within: String,
+        /// filter pattern, the same as for log
         #[arg(short, long)]
-        verbose: bool,
-        /// filter by prefix
-        #[arg(short, long, default_value = None)]
-        prefix: Option<String>,
+        filter: Option<String>,
+        /// more log args after `--`, ex: `-- --ui`
+        #[arg(last = true)]
+        log_args: Vec<String>,
+    },
+    /// print the ARN of a log group
+    Arn {
+        /// group name
+        group: String,
     },
     /// add or rewrite alias, use with with -- after alias to pass args
     Alias {
@@ -241,16 +510,128 @@ enum Commands {
     },
     /// print all aliases
     Aliases,
+    /// print version, build and config information
+    Info,
     #[command(external_subcommand)]
     External(Vec<String>),
 }
 
 #[derive(Args, Debug)]
+struct GroupsArgs {
+    /// verbose (with size)
+    #[arg(short, long)]
+    verbose: bool,
+    /// filter by pattern https://docs.rs/aws-sdk-cloudwatchlogs/latest/aws_sdk_cloudwatchlogs/operation/describe_log_groups/struct.DescribeLogGroupsInput.html#structfield.log_group_name_pattern
+    #[arg(short, long, default_value = None)]
+    pattern: Option<String>,
+    /// show streams
+    #[arg(short, long)]
+    streams: bool,
+    /// only groups created at or after the time, format is the same as for log start
+    #[arg(long, default_value = None)]
+    creation_after: Option<String>,
+    /// only groups created before the time, format is the same as for log start
+    #[arg(long, default_value = None)]
+    creation_before: Option<String>,
+    /// output format, with --streams they are nested in JSON and not supported in CSV,
+    /// the total is printed only in the text format or with --summary-only
+    #[arg(long, value_enum, default_value_t)]
+    format: output::ListFormat,
+    /// print only the number of groups and their total size
+    #[arg(long, conflicts_with_all = ["streams", "verbose"])]
+    summary_only: bool,
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+#[derive(Args, Debug, Default)]
+struct StreamsArgs {
+    /// group name
+    group: String,
+    /// verbose (with details)
+    #[arg(short, long)]
+    verbose: bool,
+    /// filter by prefix
+    #[arg(short, long, default_value = None)]
+    prefix: Option<String>,
+    /// only streams with the last event within the duration from now, the most recent first
+    #[arg(long, default_value = None)]
+    active_within: Option<String>,
+    /// sort names with numbers in them by the numbers, ex: `s-2` before `s-10`,
+    /// instead of lexically
+    #[arg(long, conflicts_with = "active_within")]
+    numeric_sort: bool,
+    /// output format
+    #[arg(long, value_enum, default_value_t)]
+    format: output::ListFormat,
+    #[command(flatten)]
+    cache: CacheArgs,
+    /// print a ready to run log command for each stream
+    #[arg(long)]
+    then_log: bool,
+    /// choose a stream from the list and show its logs
+    #[arg(long, conflicts_with_all = ["then_log", "verbose", "format"])]
+    pick: bool,
+}
+
+#[derive(Args, Clone, Debug)]
 struct LogArgs {
     /// group name
     group: String,
-    /// stream name
-    stream: String,
+    /// stream name, events of all streams of the group are merged by timestamp if it's not set
+    stream: Option<String>,
+    /// more streams to query, can be repeated, events from all of them are merged by timestamp
+    #[arg(long = "stream", value_name = "STREAM")]
+    streams: Vec<String>,
+    /// query only streams with the name prefix, events from all of them are merged by timestamp
+    #[arg(long, conflicts_with_all = ["stream", "streams", "streams_file"])]
+    stream_name_prefix: Option<String>,
+    /// file with newline-delimited stream names to query instead of a single stream,
+    /// events from all of them are merged by timestamp
+    #[arg(long, conflicts_with_all = ["stream", "streams"])]
+    streams_file: Option<PathBuf>,
+    /// query only the N streams with the most recent events instead of a single stream,
+    /// events from all of them are merged by timestamp, older streams are not searched
+    /// even if they have events in the range
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["stream", "streams", "streams_file", "stream_name_prefix"],
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    limit_streams: Option<u8>,
+    /// the number of streams fetched concurrently when all streams are merged without a filter
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    max_streams: u16,
+    /// merge lines of a local file with the events by the leading RFC 3339 or
+    /// `%Y-%m-%d %H:%M:%S` local timestamp, lines without it follow the previous line,
+    /// messages are tagged with the source, with --follow lines appended to the file or
+    /// written to a FIFO are merged as events arrive
+    #[arg(long)]
+    interleave_file: Option<PathBuf>,
+    /// read events from local files instead of CloudWatch, can be repeated, directories are
+    /// read recursively, `.gz` files are decompressed, only events in the time range are read,
+    /// the range starts from the unix epoch unless --start is set, the group is used only to
+    /// pick its config
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "stream",
+            "streams",
+            "stream_name_prefix",
+            "streams_file",
+            "limit_streams",
+            "filter",
+            "follow",
+            "cursor",
+            "progress_to",
+        ]
+    )]
+    input: Vec<PathBuf>,
+    /// format of --input files
+    #[arg(long, value_enum, default_value_t, requires = "input")]
+    input_format: input::InputFormat,
     /// start time, the time can be defines as
     /// * RFC 3339, ex:
     ///     * 2024-01-02T03:04:05.678Z
@@ -263,18 +644,42 @@ struct LogArgs {
     ///     * 12:34
     /// * UTC time of day, ex:
     ///     * 12:34Z
+    /// * local midnight of today, yesterday or the last weekday, today on the same weekday, ex:
+    ///     * yesterday
+    ///     * mon
+    /// * local date, ex:
+    ///     * 2024-01-02
     /// * Unix epoch time in seconds or milliseconds, ex:
     ///     * 1700000000
     ///     * 1700000000000
+    /// * offset back from --end or now if there is no end, ex:
+    ///     * -30m
     /// *
-    #[arg(short, long, verbatim_doc_comment, default_value_os_t = String::from("60m"))]
+    #[arg(
+        short,
+        long,
+        verbatim_doc_comment,
+        allow_hyphen_values = true,
+        default_value_os_t = String::from("60m"),
+        default_value_if("input", ArgPredicate::IsPresent, "1970-01-01T00:00:00Z")
+    )]
     start: String,
-    /// end time, format is the same as for start
+    /// end time, format is the same as for start, or `+<duration>` after the start, ex: +30m
     #[arg(short, long, default_value = None)]
     end: Option<String>,
     /// either length or end is used, the format is same as offset for start
     #[arg(short, long, default_value = None)]
     length: Option<String>,
+    /// start from the modification time of the file instead of --start
+    #[arg(long, conflicts_with = "start")]
+    since_file_mtime: Option<PathBuf>,
+    /// use the timestamp at the message start in the strftime format, ex: '%Y-%m-%d %H:%M:%S%.f',
+    /// instead of the CloudWatch one for display and sorting, local time if there is no offset
+    #[arg(long)]
+    tz_from_message: Option<String>,
+    /// round start down and end up to a local time boundary
+    #[arg(long, value_enum)]
+    align_to: Option<time_arg::Align>,
     /// AWS CloudWatch filter https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html
     /// Examples:
     /// * 'A B' - the log has to contain A and B
@@ -282,23 +687,343 @@ struct LogArgs {
     /// * '?A ?B' - matches with A or B
     /// * 'A -B' - matches with lines contains A and no B
     /// * '%\s[A-Z]{4}\s%' - regex
+    ///
+    /// The filter is case sensitive, use --grep-i for case insensitive matching
     #[arg(short, long, verbatim_doc_comment, default_value = None)]
     filter: Option<String>,
-    /// replace regexp
+    /// keep only events matching the regex case insensitive, checked on the client side
+    /// after --message-regexp, can be repeated, all regexes have to match
+    #[arg(long, verbatim_doc_comment)]
+    grep_i: Vec<String>,
+    /// keep only events matching the regex, checked on the client side
+    /// after --message-regexp, can be repeated, all regexes have to match
+    #[arg(long, verbatim_doc_comment)]
+    grep: Vec<String>,
+    /// drop events matching the regex, checked on the client side
+    /// after --message-regexp, can be repeated, an event is kept if none match
+    #[arg(long, verbatim_doc_comment)]
+    grep_invert: Vec<String>,
+    /// remove a leading timestamp in the strftime format from messages, the format is set
+    /// with `=`, ex: --strip-timestamp-prefix='%Y-%m-%d %H:%M:%S', without a format RFC 3339
+    /// and common `%Y-%m-%d %H:%M:%S` variants are removed
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true)]
+    strip_timestamp_prefix: Option<Option<String>>,
+    /// for JSON messages print only the fields as `path=value`, nested fields are separated
+    /// by dot, ex: `user.id,req.path`, other messages are printed as is
+    #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+    fields: Vec<String>,
+    /// for JSON messages print all nested fields as `path=value`
+    #[arg(long, conflicts_with = "fields")]
+    flatten_json: bool,
+    /// replace regexp, can be repeated, the rules are applied left to right
     /// '<delimiter char><regexp><the same delimiter char><replacement>'
     /// example: '/(\d{4} [^|]+/$1'
-    #[arg(short='r', long, verbatim_doc_comment, default_value = None)]
-    message_regexp: Option<String>,
+    /// the first match is replaced, add the delimiter and `g` to replace all,
+    /// example: '/\d+/N/g'
+    /// groups are referenced by index or name, use braces if letters follow,
+    /// example: '/(?P<ts>\d+) (?P<lvl>\w+)/${lvl} ${ts}'
+    #[arg(short = 'r', long, verbatim_doc_comment)]
+    message_regexp: Vec<String>,
+    /// drop events which are empty after the --message-regexp replacement,
+    /// unlike --skip-empty, which checks the original message,
+    /// ex: `-r '/^health-check.*$/' --regexp-drop-empty` hides health checks
+    #[arg(long, requires = "message_regexp")]
+    regexp_drop_empty: bool,
+    /// show control chars in messages as visible text, ex: `^@`, `^[`, to protect the terminal
+    #[arg(long)]
+    escape_control: bool,
+    /// cut messages longer than the number of chars, the original length is added
+    #[arg(long, default_value = None)]
+    max_message_length: Option<usize>,
     /// ouput datetime format https://docs.rs/chrono/latest/chrono/format/strftime/index.html
     #[arg[short, long, default_value_t = String::from("%d%b %H:%M:%S%.3f")]]
     datetime_format: String,
+    /// separator of the datetime and the message in the text output
+    #[arg(long, default_value = "|")]
+    separator: String,
+    /// terse datetime format, `%H:%M:%S` if all events are from today, otherwise with date,
+    /// events are buffered to check it unless the whole range is today, with --follow
+    /// the range is checked instead
+    #[arg(long, conflicts_with = "datetime_format")]
+    compact: bool,
+    /// print the epoch time after the datetime in the text output, `<datetime> (<epoch>)|message`
+    #[arg(long)]
+    both_times: bool,
+    /// print the time since the first event, ex: `+1.234s`, instead of the datetime in the text
+    /// output, with --both-times it's printed instead of the epoch time after the datetime
+    #[arg(long)]
+    relative_timestamps: bool,
+    /// print the event ID after the datetime and add it to JSON records, it's empty
+    /// for events of a single stream without a filter, which are fetched by GetLogEvents
+    #[arg(long)]
+    show_event_id: bool,
+    /// unit of the epoch time for --both-times
+    #[arg(long, value_enum, requires = "both_times", default_value_t = output::EpochUnit::Ms)]
+    epoch_unit: output::EpochUnit,
+    /// drop events without a message or with an empty one
+    #[arg(long)]
+    skip_empty: bool,
+    /// drop events with a detected level below, the order is TRACE < DEBUG < INFO < WARN < ERROR
+    #[arg(long, ignore_case = true, default_value = None)]
+    min_level: Option<level::Level>,
+    /// what to do with events without a detected level when --min-level is used
+    #[arg(long, value_enum, default_value_t = level::Unlevelled::Keep)]
+    unlevelled: level::Unlevelled,
 
     #[cfg(feature = "ui")]
     /// show results in UI
     #[arg(short, long, default_value_t = false)]
     ui: bool,
 
+    /// color the datetime and tint messages by the detected level in the text output
+    #[arg(long, value_enum, default_value_t)]
+    color: output::ColorMode,
+    /// output format
+    #[arg(short, long, value_enum, default_value_t = output::OutputFormat::Text)]
+    output: output::OutputFormat,
+    /// JSON indentation, 0 prints every record on a single line
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=8))]
+    json_indent: u8,
+    /// columns of the CSV output in the order
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = output::CsvColumn::DEFAULT)]
+    csv_columns: Vec<output::CsvColumn>,
+    /// fields of JSON records as `<output key>=<field>` or `<field>`, ex: `ts=timestamp,msg=message`,
+    /// fields are the same as --csv-columns, `timestamp` is an alias of `timestamp_ms`
+    #[arg(long, value_delimiter = ',', value_parser = output::parse_json_field)]
+    json_fields: Vec<(String, output::CsvColumn)>,
+    /// advanced: write only message bytes, each followed by --raw-delimiter,
+    /// no datetime, format or encoding handling is applied
+    #[arg(long, conflicts_with_all = ["output", "json_indent"])]
+    raw_bytes: bool,
+    /// delimiter for --raw-bytes, supports \n, \r, \t, \0, \\ and \xHH escapes
+    #[arg(long, requires = "raw_bytes", default_value = "\\n")]
+    raw_delimiter: String,
+    /// forward events to the local syslog socket instead of printing them,
+    /// the severity is mapped from the detected level
+    #[cfg(all(unix, feature = "syslog"))]
+    #[arg(long, conflicts_with_all = ["output", "raw_bytes", "tee"])]
+    to_syslog: bool,
+    /// forward events to journald instead of printing them,
+    /// the severity is mapped from the detected level
+    #[cfg(all(target_os = "linux", feature = "syslog"))]
+    #[arg(long, conflicts_with_all = ["to_syslog", "output", "raw_bytes", "tee"])]
+    to_journald: bool,
+    /// syslog facility of forwarded events
+    #[cfg(all(unix, feature = "syslog"))]
+    #[arg(long, value_enum, default_value_t = syslog::Facility::User)]
+    syslog_facility: syslog::Facility,
+    /// syslog severity of forwarded events without a detected level
+    #[cfg(all(unix, feature = "syslog"))]
+    #[arg(long, value_enum, default_value_t = syslog::Severity::Info)]
+    syslog_severity: syslog::Severity,
+
+    /// don't page the output, by default it's paged with $PAGER or less if stdout is a terminal,
+    /// the env var accepts true/false, 1/0, yes/no or on/off
+    #[arg(
+        long,
+        env = "AXE_NO_PAGER",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    no_pager: bool,
+    /// buffer the output up to the size in bytes, 0 writes every event at once,
+    /// a large buffer is faster for bulk exports
+    #[arg(long, default_value_t = 0)]
+    output_buffer_size: usize,
+    /// write the buffered output after the number of events
+    #[arg(long, value_name = "N", requires = "output_buffer_size")]
+    flush_every: Option<usize>,
+    /// write the buffered output if the duration passed since the last write,
+    /// it's checked when an event is printed
+    #[arg(long, requires = "output_buffer_size")]
+    flush_interval: Option<String>,
+    /// also append the output to the file
+    #[arg(long, default_value = None)]
+    tee: Option<PathBuf>,
+    /// rotate the --tee file when it reaches a size (ex: 100MB) or age (ex: 1h),
+    /// rotated files are gzipped as <name>.<n>.<ext>.gz
+    #[arg(long, requires = "tee", verbatim_doc_comment, default_value = None)]
+    output_rotate: Option<String>,
+
+    /// buffer events for the duration and print them sorted by timestamp, format is same as length
+    #[arg(long, default_value = None)]
+    local_sort_window: Option<String>,
+
+    /// with a filter, fetch all events and sort them by timestamp and stream name,
+    /// events are printed only after the last page
+    #[arg(long, requires = "filter")]
+    merge_sort_across_pages: bool,
+
+    /// drop events until the first one matching the regex, it and all the next events are printed
+    #[arg(long, default_value = None)]
+    drop_before_first_match: Option<String>,
+    /// print the newest events first, events are buffered unless a single stream is queried
+    /// without a filter, with --limit N the N newest events are printed
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "local_sort_window", "interleave_file", "cursor", "progress_to"]
+    )]
+    reverse: bool,
+    /// stop after printing N events, counted after the client side filtering
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    limit: Option<u64>,
+    /// stop after the first event matching the regex, fail if there is no such event
+    #[arg(long, default_value = None)]
+    until_match: Option<String>,
+    /// with --until-match, fail if no event matched within the duration
+    #[arg(long, requires = "until_match", default_value = None)]
+    timeout: Option<String>,
+    /// ring the terminal bell when an event matches the regex,
+    /// at most once per 5 seconds
+    #[arg(long, default_value = None)]
+    notify_on: Option<String>,
+
+    /// keep polling for new events after the range, the end is the current time,
+    /// events ingested after a poll with an older timestamp than the last printed one are missed,
+    /// with --ui new events are appended to the table
+    #[arg(long, conflicts_with_all = ["end", "length", "cursor", "progress_to"])]
+    follow: bool,
+    /// interval between --follow polls
+    #[arg(long, requires = "follow", default_value = "5s")]
+    poll_interval: String,
+
+    /// count printed events by the first capture group of the regex and print the counts
+    /// to stderr at the end, the most frequent first, ex: `status=(\d+)`
+    #[arg(long)]
+    count_by: Option<String>,
+
+    /// print to stderr whether all events in the range are returned
+    #[arg(long)]
+    report: bool,
+
     /// number records in a chunk, maximum is 10k
     #[arg(long, default_value_t = 1000)]
     chunk_size: u16,
+    /// periodically save the next page token and the last timestamp to the file as JSON,
+    /// to resume an interrupted dump with the same arguments and --cursor
+    #[arg(long, conflicts_with = "merge_sort_across_pages")]
+    progress_to: Option<PathBuf>,
+    /// start from the page token, ex: `next_token` from the --progress-to file
+    #[arg(long)]
+    cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_error_position() {
+        let text = "[alias]\na = [\"log\"\nb = 1\n";
+        let error = text.parse::<toml_edit::DocumentMut>().unwrap_err();
+        let message = config_parse_error(text, &PathBuf::from("axe.toml"), &error).to_string();
+        assert!(
+            message.starts_with("config parse failed at axe.toml:3:"),
+            "{message}"
+        );
+        assert!(message.contains("   3 | b = 1"), "{message}");
+    }
+
+    #[test]
+    fn non_string_alias_values() {
+        let config = "[alias]\na = [\"log\", \"--chunk-size\", 100, true]\nb = [[1]]\n"
+            .parse::<toml_edit::DocumentMut>()
+            .unwrap();
+        let alias = |name| config["alias"][name].as_array().unwrap();
+        assert_eq!(
+            alias_args(alias("a")).unwrap(),
+            ["log", "--chunk-size", "100", "true"]
+        );
+        assert!(alias_args(alias("b")).is_err());
+    }
+
+    #[test]
+    fn log_command_client_args() {
+        let cli = Cli::try_parse_from([
+            "axe",
+            "-p",
+            "profile",
+            "--region",
+            "eu-west-1",
+            "--app-name",
+            "app",
+            "--fips",
+            "--max-attempts",
+            "5",
+            "--retry-mode",
+            "adaptive",
+            "recent",
+            "group",
+        ])
+        .unwrap();
+        let reparsed = Cli::try_parse_from(log_command(&cli.client, "group")).unwrap();
+        assert_eq!(
+            format!("{:?}", reparsed.client),
+            format!("{:?}", cli.client)
+        );
+        assert!(matches!(reparsed.command, Some(Commands::Log(args)) if args.group == "group"));
+    }
+
+    #[test]
+    fn strip_timestamp_prefix_value() {
+        let strip = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["axe", "log"].iter().chain(args)).unwrap();
+            match cli.command {
+                Some(Commands::Log(args)) => (args.group, args.strip_timestamp_prefix),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            strip(&["--strip-timestamp-prefix", "group"]),
+            ("group".into(), Some(None))
+        );
+        assert_eq!(
+            strip(&["--strip-timestamp-prefix=%H:%M", "group"]),
+            ("group".into(), Some(Some("%H:%M".into())))
+        );
+    }
+
+    #[test]
+    fn input_start() {
+        let start = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["axe", "log", "group"].iter().chain(args)).unwrap();
+            match cli.command {
+                Some(Commands::Log(args)) => args.start,
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(start(&[]), "60m");
+        assert_eq!(start(&["--input", "dir"]), "1970-01-01T00:00:00Z");
+        assert_eq!(start(&["--input", "dir", "-s", "2h"]), "2h");
+    }
+
+    #[test]
+    fn no_pager_env() {
+        let no_pager = |value| {
+            env::set_var("AXE_NO_PAGER", value);
+            let cli = Cli::try_parse_from(["axe", "log", "group"]);
+            env::remove_var("AXE_NO_PAGER");
+            match cli.unwrap().command {
+                Some(Commands::Log(args)) => args.no_pager,
+                _ => unreachable!(),
+            }
+        };
+        assert!(no_pager("1"));
+        assert!(no_pager("yes"));
+        assert!(!no_pager("0"));
+    }
+
+    #[test]
+    fn list_non_string_aliases() {
+        let config = "[alias]\na = [\"log\", \"--chunk-size\", 100]\n"
+            .parse::<toml_edit::DocumentMut>()
+            .unwrap();
+        assert_eq!(
+            alias_lines(&config).unwrap(),
+            ["a\t\"log\" \"--chunk-size\" \"100\""]
+        );
+        let config = "[alias]\nb = [[1]]\n"
+            .parse::<toml_edit::DocumentMut>()
+            .unwrap();
+        assert!(alias_lines(&config).is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use aws_sdk_cloudwatchlogs as cloudwatchlogs;
+use cloudwatchlogs::{
+    operation::get_log_events::builders::GetLogEventsInputBuilder, types::OutputLogEvent,
+};
+use log::debug;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    cache::Cache,
+    log::{Completion, EventInfo},
+    streams,
+    utils::{group_identifier, ApiContext, Operation},
+    LogArgs, StreamsArgs,
+};
+
+/// Buffered page of a stream
+struct StreamEvents {
+    name: String,
+    events: VecDeque<OutputLogEvent>,
+    /// None if there are no more pages
+    next_token: Option<String>,
+}
+
+/// Fetches events of all streams of the group with events in the range
+/// and passes them to the consumer ordered by timestamp
+pub async fn print_merged_events<ConsumerFn>(
+    client: &cloudwatchlogs::Client,
+    args: &LogArgs,
+    start: i64,
+    end: i64,
+    consumer: &mut ConsumerFn,
+) -> Result<Completion>
+where
+    ConsumerFn: FnMut(Option<i64>, Option<String>, EventInfo) -> bool,
+{
+    let streams_args = StreamsArgs {
+        group: args.group.clone(),
+        ..Default::default()
+    };
+    let names = streams::list(client, &Cache::default(), &streams_args)
+        .await?
+        .into_iter()
+        .filter(|s| {
+            s.first_event_timestamp.is_none_or(|t| t < end)
+                && s.last_ingestion_time
+                    .or(s.last_event_timestamp)
+                    .is_none_or(|t| t >= start)
+        })
+        .filter_map(|s| s.log_stream_name)
+        .collect::<Vec<_>>();
+    debug!("merge events of {} streams", names.len());
+
+    // the first pages are fetched concurrently, then a page is fetched when a stream runs out
+    let semaphore = Arc::new(Semaphore::new(args.max_streams as usize));
+    let mut tasks = JoinSet::new();
+    for (index, name) in names.into_iter().enumerate() {
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        let template = template(args, &name, start, end);
+        let group = args.group.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("semaphore is open");
+            let page = fetch_page(&client, &group, template, None).await;
+            (index, name, page)
+        });
+    }
+    let mut first_pages = vec![];
+    while let Some(result) = tasks.join_next().await {
+        let (index, name, page) = result.context("stream task failed")?;
+        let (events, next_token) = page?;
+        first_pages.push((
+            index,
+            StreamEvents {
+                name,
+                events,
+                next_token,
+            },
+        ));
+    }
+    first_pages.sort_by_key(|(index, _)| *index);
+    let mut streams = first_pages.into_iter().map(|(_, s)| s).collect::<Vec<_>>();
+
+    let mut heap = BinaryHeap::new();
+    for (index, stream) in streams.iter().enumerate() {
+        if let Some(event) = stream.events.front() {
+            heap.push(Reverse((event.timestamp.unwrap_or_default(), index)));
+        }
+    }
+    while let Some(Reverse((_, index))) = heap.pop() {
+        let stream = &mut streams[index];
+        let event = stream
+            .events
+            .pop_front()
+            .expect("stream in the heap has events");
+        let info = EventInfo {
+            stream: Some(stream.name.clone()),
+            event_id: None,
+            ingestion_time: event.ingestion_time,
+        };
+        if !consumer(event.timestamp, event.message, info) {
+            return Ok(Completion::Stopped);
+        }
+        if stream.events.is_empty() {
+            if let Some(token) = stream.next_token.take() {
+                let template = template(args, &stream.name, start, end);
+                (stream.events, stream.next_token) =
+                    fetch_page(client, &args.group, template, Some(token)).await?;
+            }
+        }
+        if let Some(event) = stream.events.front() {
+            heap.push(Reverse((event.timestamp.unwrap_or_default(), index)));
+        }
+    }
+    Ok(Completion::Complete)
+}
+
+fn template(args: &LogArgs, stream: &str, start: i64, end: i64) -> GetLogEventsInputBuilder {
+    GetLogEventsInputBuilder::default()
+        .log_group_identifier(group_identifier(&args.group))
+        .log_stream_name(stream)
+        .limit(args.chunk_size as i32)
+        .start_from_head(true)
+        .start_time(start)
+        .end_time(end)
+}
+
+/// Returns the page events and the token of the next page if there is one
+async fn fetch_page(
+    client: &cloudwatchlogs::Client,
+    group: &str,
+    template: GetLogEventsInputBuilder,
+    token: Option<String>,
+) -> Result<(VecDeque<OutputLogEvent>, Option<String>)> {
+    let output = template
+        .set_next_token(token.clone())
+        .send_with(client)
+        .await
+        .api_context(Operation::GetLogEvents, Some(group))?;
+    let events = output.events.unwrap_or_default();
+    // the forward token doesn't change at the end of the stream
+    let next_token = output
+        .next_forward_token
+        .filter(|t| !events.is_empty() && Some(t) != token.as_ref());
+    Ok((events.into(), next_token))
+}
@@ -0,0 +1,935 @@
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    io::{self, stdout, ErrorKind, IsTerminal, Write},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::SecondsFormat;
+use clap::ValueEnum;
+use log::debug;
+use serde::Serialize;
+use serde_json::{json, ser::PrettyFormatter, Serializer, Value};
+
+use crate::{
+    level::{self, Level},
+    log::EventInfo,
+    rotate::RotatingWriter,
+    utils::local_time,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// `<datetime>|<message>`
+    Text,
+    /// array of objects with timestamp, datetime, message and ingestionTime if it's known
+    Json,
+    /// compact JSON object per line, written as soon as the event arrives
+    Ndjson,
+    /// `time=<RFC 3339 local time> msg=<message>`
+    Logfmt,
+    /// CSV with a header, `timestamp_iso,timestamp_ms,stream,message` columns by default,
+    /// the time is local, stream is empty if several streams are queried
+    Csv,
+}
+
+/// Column of the CSV output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum CsvColumn {
+    /// RFC 3339 local time
+    TimestampIso,
+    /// unix time in ms
+    #[value(alias = "timestamp")]
+    TimestampMs,
+    /// time in --datetime-format
+    Datetime,
+    Stream,
+    Message,
+    /// ID of the event, only FilterLogEvents returns it, ex: with a filter or several streams
+    EventId,
+}
+
+impl CsvColumn {
+    pub const DEFAULT: [CsvColumn; 4] = [
+        CsvColumn::TimestampIso,
+        CsvColumn::TimestampMs,
+        CsvColumn::Stream,
+        CsvColumn::Message,
+    ];
+}
+
+/// Parses a `--json-fields` item `<output key>=<field>` or `<field>`,
+/// fields are the same as CSV columns, `timestamp` is an alias of `timestamp_ms`
+pub fn parse_json_field(value: &str) -> Result<(String, CsvColumn)> {
+    let (key, field) = value.split_once('=').unwrap_or((value, value));
+    if key.is_empty() {
+        bail!("empty output key in `{value}`");
+    }
+    let field = CsvColumn::from_str(field, false).map_err(|_| {
+        let fields = CsvColumn::value_variants()
+            .iter()
+            .filter_map(|c| c.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect::<Vec<_>>();
+        anyhow::anyhow!(
+            "unknown field `{field}`, possible fields: timestamp, {}",
+            fields.join(", ")
+        )
+    })?;
+    Ok((key.to_string(), field))
+}
+
+/// When the buffered output is written and the tee file is flushed, whichever comes first,
+/// the interval is checked when a record is printed
+#[derive(Debug, Default)]
+pub struct FlushPolicy {
+    /// 0 writes every record at once
+    pub buffer_size: usize,
+    /// the number of records
+    pub every: Option<usize>,
+    pub interval: Option<Duration>,
+}
+
+/// When to color the text output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// if stdout is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => {
+                stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Dims the datetime prefix and tints the message by the detected level
+fn color_text(prefix: &str, separator: &str, message: &str) -> String {
+    let color = match level::detect(message) {
+        Some(Level::Error) => "\x1b[31m",
+        Some(Level::Warn) => "\x1b[33m",
+        Some(Level::Info) => "\x1b[32m",
+        Some(Level::Debug | Level::Trace) => "\x1b[34m",
+        None => return format!("{DIM}{prefix}{RESET}{separator}{message}\n"),
+    };
+    format!("{DIM}{prefix}{RESET}{separator}{color}{message}{RESET}\n")
+}
+
+/// Formats the offset in ms as `+1.234s`
+fn relative_time(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.unsigned_abs();
+    format!("{sign}{}.{:03}s", offset / 1000, offset % 1000)
+}
+
+/// Unit of the epoch time printed with --both-times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EpochUnit {
+    Ms,
+    S,
+}
+
+/// Format of groups and streams listings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// human readable lines
+    #[default]
+    Text,
+    /// array of objects
+    Json,
+    /// an object per line
+    Ndjson,
+    /// comma separated values with a header
+    Csv,
+}
+
+/// Prints listing records in the chosen format
+pub struct ListPrinter {
+    format: ListFormat,
+    records: Vec<Value>,
+    csv: csv::Writer<io::Stdout>,
+    header_written: bool,
+}
+
+impl ListPrinter {
+    pub fn new(format: ListFormat) -> Self {
+        Self {
+            format,
+            records: vec![],
+            csv: csv::Writer::from_writer(stdout()),
+            header_written: false,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == ListFormat::Text
+    }
+
+    /// Prints `text` in the text format and `record` object otherwise
+    pub fn print(&mut self, text: &str, record: Value) -> Result<()> {
+        match self.format {
+            ListFormat::Text => print_line(text)?,
+            ListFormat::Json => self.records.push(record),
+            ListFormat::Ndjson => print_line(record)?,
+            ListFormat::Csv => {
+                let Value::Object(fields) = record else {
+                    bail!("csv record has to be an object");
+                };
+                if !self.header_written {
+                    self.csv.write_record(fields.keys()).map_err(csv_error)?;
+                    self.header_written = true;
+                }
+                let values = fields
+                    .values()
+                    .map(|v| match v {
+                        Value::Null => Ok(String::new()),
+                        Value::String(s) => Ok(s.clone()),
+                        Value::Bool(_) | Value::Number(_) => Ok(v.to_string()),
+                        Value::Array(_) | Value::Object(_) => {
+                            bail!("nested values are not supported in csv")
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.csv.write_record(values).map_err(csv_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the JSON array and flushes CSV, should be called after the last record
+    pub fn finish(mut self) -> Result<()> {
+        match self.format {
+            ListFormat::Json => print_line(serde_json::to_string_pretty(&self.records)?)?,
+            ListFormat::Csv => self.csv.flush().map_err(output_error)?,
+            ListFormat::Text | ListFormat::Ndjson => {}
+        }
+        Ok(())
+    }
+}
+
+/// The reader of stdout is gone, ex: with `| head`, it ends the command without an error
+#[derive(Debug)]
+pub struct OutputClosed;
+
+impl Display for OutputClosed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "output is closed")
+    }
+}
+
+impl std::error::Error for OutputClosed {}
+
+/// Writes the line to stdout, a closed pipe is returned as `OutputClosed` instead of a panic
+pub fn print_line(line: impl Display) -> Result<()> {
+    writeln!(stdout().lock(), "{line}").map_err(output_error)
+}
+
+/// Writes the text without a new line and flushes it, ex: a prompt
+pub fn print_text(text: impl Display) -> Result<()> {
+    let mut stdout = stdout().lock();
+    write!(stdout, "{text}")
+        .and_then(|_| stdout.flush())
+        .map_err(output_error)
+}
+
+fn output_error(e: io::Error) -> anyhow::Error {
+    if e.kind() == ErrorKind::BrokenPipe {
+        OutputClosed.into()
+    } else {
+        anyhow::Error::new(e).context("cannot write output")
+    }
+}
+
+fn csv_error(e: csv::Error) -> anyhow::Error {
+    if e.is_io_error() {
+        if let csv::ErrorKind::Io(e) = e.into_kind() {
+            return output_error(e);
+        }
+        unreachable!("io csv error");
+    }
+    anyhow::Error::new(e).context("cannot write csv")
+}
+
+/// Writes events to stdout and optionally to a file in the chosen format
+pub struct Printer<'a> {
+    format: OutputFormat,
+    datetime_format: &'a str,
+    json_indent: usize,
+    count: usize,
+    tee: Option<RotatingWriter>,
+    pager: Option<Child>,
+    /// between the datetime and the message in the text format
+    separator: &'a str,
+    /// color the text format with ANSI codes
+    color: bool,
+    /// print the epoch time after the datetime in the text format
+    epoch_unit: Option<EpochUnit>,
+    /// print the offset from the first event instead of the datetime in the text format,
+    /// or instead of the epoch time with `epoch_unit`
+    relative: bool,
+    first_timestamp: Option<i64>,
+    /// print the event ID after the datetime in the text and logfmt formats,
+    /// add it to the default JSON record
+    event_id: bool,
+    /// stream name for the CSV stream column, None if several streams are queried
+    stream: Option<String>,
+    csv_columns: Vec<CsvColumn>,
+    /// output keys and fields of JSON records, None for the default record
+    json_fields: Option<Vec<(String, CsvColumn)>>,
+    /// write messages as is, each followed by the delimiter
+    raw_delimiter: Option<Vec<u8>>,
+    flush: FlushPolicy,
+    /// records not written to the output yet
+    buffer: Vec<u8>,
+    buffered_records: usize,
+    last_flush: Instant,
+    error: Option<io::Error>,
+    /// the reader closed the output, ex: the pager is closed
+    closed: bool,
+    /// written records are kept here instead of stdout, ex: to save them from the UI
+    memory: Option<Vec<u8>>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(
+        format: OutputFormat,
+        datetime_format: &'a str,
+        json_indent: usize,
+        tee: Option<RotatingWriter>,
+    ) -> Self {
+        Self {
+            format,
+            datetime_format,
+            json_indent,
+            count: 0,
+            tee,
+            pager: None,
+            separator: "|",
+            color: false,
+            epoch_unit: None,
+            relative: false,
+            event_id: false,
+            first_timestamp: None,
+            stream: None,
+            csv_columns: CsvColumn::DEFAULT.to_vec(),
+            json_fields: None,
+            raw_delimiter: None,
+            flush: FlushPolicy::default(),
+            buffer: vec![],
+            buffered_records: 0,
+            last_flush: Instant::now(),
+            error: None,
+            closed: false,
+            memory: None,
+        }
+    }
+
+    /// Writes to the pager stdin instead of stdout
+    pub fn with_pager(mut self, pager: Option<Child>) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    /// Prints `<datetime> (<epoch>)|<message>` in the text format
+    pub fn with_epoch(mut self, unit: Option<EpochUnit>) -> Self {
+        self.epoch_unit = unit;
+        self
+    }
+
+    /// Sets the separator of the datetime and the message in the text format
+    pub fn with_separator(mut self, separator: &'a str) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Prints `+<seconds>s` since the first event instead of the datetime in the text format,
+    /// `<datetime> (+<seconds>s)` if the epoch time is printed too
+    pub fn with_relative(mut self, relative: bool) -> Self {
+        self.relative = relative;
+        self
+    }
+
+    /// Prints the event ID, empty if the API didn't return it
+    pub fn with_event_id(mut self, event_id: bool) -> Self {
+        self.event_id = event_id;
+        self
+    }
+
+    /// Colors the text format, other formats are never colored
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Buffers the output, by default every record is written at once
+    pub fn with_flush(mut self, flush: FlushPolicy) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Sets CSV columns and their order
+    pub fn with_csv_columns(mut self, columns: Vec<CsvColumn>) -> Self {
+        self.csv_columns = columns;
+        self
+    }
+
+    /// Selects and renames fields of JSON records
+    pub fn with_json_fields(mut self, fields: Option<Vec<(String, CsvColumn)>>) -> Self {
+        self.json_fields = fields;
+        self
+    }
+
+    /// Sets the stream name for the CSV stream column
+    pub fn with_stream(mut self, stream: Option<String>) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Sets the stream of the next records, ex: when they are from different streams
+    #[cfg(any(feature = "ui", test))]
+    pub fn set_stream(&mut self, stream: Option<String>) {
+        self.stream = stream;
+    }
+
+    /// Keeps the output in memory to be taken by `into_output` instead of writing to stdout
+    #[cfg(any(feature = "ui", test))]
+    pub fn with_memory_output(mut self) -> Self {
+        self.memory = Some(vec![]);
+        self
+    }
+
+    /// Returns the output kept in memory, should be called after `finish`
+    #[cfg(any(feature = "ui", test))]
+    pub fn into_output(self) -> Vec<u8> {
+        self.memory.unwrap_or_default()
+    }
+
+    /// Writes only message bytes followed by the delimiter, ignoring the format
+    pub fn with_raw_delimiter(mut self, delimiter: Option<Vec<u8>>) -> Self {
+        self.raw_delimiter = delimiter;
+        self
+    }
+
+    /// Returns false if writing failed, the error is returned by `finish`
+    pub fn print(&mut self, timestamp: Option<i64>, message: &str, info: &EventInfo) -> bool {
+        let event_id = info.event_id.as_deref();
+        if let Some(delimiter) = &self.raw_delimiter {
+            let record = [message.as_bytes(), delimiter].concat();
+            return self.write(&record);
+        }
+        let datetime = local_time(timestamp.unwrap_or(0)).format(self.datetime_format);
+        // the colored text goes only to the terminal, the tee file gets the plain one
+        let mut colored = None;
+        let record = match self.format {
+            OutputFormat::Text => {
+                let offset = timestamp
+                    .filter(|_| self.relative)
+                    .map(|t| relative_time(t - *self.first_timestamp.get_or_insert(t)));
+                let prefix = match (offset, self.epoch_unit, timestamp) {
+                    (Some(offset), Some(_), _) => format!("{datetime} ({offset})"),
+                    (Some(offset), None, _) => offset,
+                    (None, Some(EpochUnit::Ms), Some(t)) => format!("{datetime} ({t})"),
+                    (None, Some(EpochUnit::S), Some(t)) => format!("{datetime} ({})", t / 1000),
+                    _ => datetime.to_string(),
+                };
+                let prefix = if self.event_id {
+                    format!("{prefix}{}{}", self.separator, event_id.unwrap_or_default())
+                } else {
+                    prefix
+                };
+                if self.color {
+                    colored = Some(color_text(&prefix, self.separator, message));
+                }
+                format!("{prefix}{}{message}\n", self.separator)
+            }
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                let record = self.json_record(timestamp, datetime.to_string(), message, info);
+                if self.format == OutputFormat::Ndjson {
+                    format!("{record}\n")
+                } else {
+                    // a record per line with --json-indent 0, the comma ends the previous record line
+                    let separator = if self.count == 0 { "[" } else { "," };
+                    format!("{separator}\n{}", self.to_json(&record))
+                }
+            }
+            OutputFormat::Logfmt => {
+                let time = timestamp
+                    .map(|t| local_time(t).to_rfc3339_opts(SecondsFormat::Millis, true))
+                    .unwrap_or_default();
+                let id = if self.event_id {
+                    format!(" id={}", logfmt_value(event_id.unwrap_or_default()))
+                } else {
+                    String::new()
+                };
+                format!(
+                    "time={}{id} msg={}\n",
+                    logfmt_value(&time),
+                    logfmt_value(message)
+                )
+            }
+            OutputFormat::Csv => {
+                let fields = self
+                    .csv_columns
+                    .iter()
+                    .map(|c| match c {
+                        CsvColumn::TimestampIso => timestamp
+                            .map(|t| local_time(t).to_rfc3339_opts(SecondsFormat::Millis, true))
+                            .unwrap_or_default(),
+                        CsvColumn::TimestampMs => {
+                            timestamp.map(|t| t.to_string()).unwrap_or_default()
+                        }
+                        CsvColumn::Datetime => datetime.to_string(),
+                        CsvColumn::Stream => self.stream.clone().unwrap_or_default(),
+                        CsvColumn::Message => message.to_string(),
+                        CsvColumn::EventId => event_id.unwrap_or_default().to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                let header = if self.count == 0 {
+                    self.csv_header()
+                } else {
+                    String::new()
+                };
+                format!("{header}{}", to_csv(&fields))
+            }
+        };
+        self.count += 1;
+        match colored {
+            Some(colored) => self.write_to(colored.as_bytes(), record.as_bytes()),
+            None => self.write(record.as_bytes()),
+        }
+    }
+
+    /// Closes the JSON array, should be called after the last event
+    pub fn finish(&mut self) -> Result<()> {
+        if self.raw_delimiter.is_none() {
+            match self.format {
+                OutputFormat::Json => {
+                    let end = if self.count == 0 { "[]\n" } else { "\n]\n" };
+                    self.write(end.as_bytes());
+                }
+                OutputFormat::Csv if self.count == 0 => {
+                    let header = self.csv_header();
+                    self.write(header.as_bytes());
+                }
+                OutputFormat::Text
+                | OutputFormat::Ndjson
+                | OutputFormat::Logfmt
+                | OutputFormat::Csv => {}
+            }
+        }
+        if !self.buffer.is_empty() && self.error.is_none() && !self.closed {
+            let result = self.flush_buffer();
+            self.check(result);
+        }
+        if let Some(tee) = &mut self.tee {
+            if let Err(e) = tee.flush() {
+                self.error.get_or_insert(e);
+            }
+        }
+        if let Some(mut pager) = self.pager.take() {
+            drop(pager.stdin.take());
+            pager.wait().context("pager failed")?;
+        }
+        match self.error.take() {
+            Some(e) => Err(e).context("cannot write output"),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&mut self, text: &[u8]) -> bool {
+        self.write_to(text, text)
+    }
+
+    /// Writes `output` to stdout or the pager and `file` to the tee file
+    fn write_to(&mut self, output: &[u8], file: &[u8]) -> bool {
+        if self.error.is_some() || self.closed {
+            return false;
+        }
+        self.buffer.extend_from_slice(output);
+        self.buffered_records += 1;
+        let result = self
+            .tee
+            .as_mut()
+            .map_or(Ok(()), |tee| tee.write_record(file))
+            .and_then(|_| {
+                if self.flush_due() {
+                    self.flush_buffer()
+                } else {
+                    Ok(())
+                }
+            });
+        self.check(result)
+    }
+
+    fn flush_due(&self) -> bool {
+        self.buffer.len() >= self.flush.buffer_size
+            || self.flush.every.is_some_and(|n| self.buffered_records >= n)
+            || self
+                .flush
+                .interval
+                .is_some_and(|i| self.last_flush.elapsed() >= i)
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        self.buffered_records = 0;
+        self.last_flush = Instant::now();
+        let result = match (
+            &mut self.memory,
+            self.pager.as_mut().and_then(|p| p.stdin.as_mut()),
+        ) {
+            (Some(memory), _) => {
+                memory.extend_from_slice(&self.buffer);
+                Ok(())
+            }
+            (None, Some(stdin)) => stdin.write_all(&self.buffer),
+            (None, None) => {
+                let mut stdout = stdout().lock();
+                stdout.write_all(&self.buffer).and_then(|_| stdout.flush())
+            }
+        };
+        self.buffer.clear();
+        // the tee file follows stdout, ex: with --follow
+        result.and_then(|_| self.tee.as_mut().map_or(Ok(()), RotatingWriter::flush))
+    }
+
+    /// Returns false and keeps the error if writing failed
+    fn check(&mut self, result: io::Result<()>) -> bool {
+        match result {
+            Ok(_) => true,
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                debug!("output is closed");
+                self.closed = true;
+                false
+            }
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    fn json_record(
+        &self,
+        timestamp: Option<i64>,
+        datetime: String,
+        message: &str,
+        info: &EventInfo,
+    ) -> Value {
+        let event_id = info.event_id.as_deref();
+        let Some(fields) = &self.json_fields else {
+            let mut record = json!({
+                "timestamp": timestamp,
+                "datetime": datetime,
+                "message": message,
+            });
+            if self.event_id {
+                record["event_id"] = json!(event_id);
+            }
+            if let Some(ingestion_time) = info.ingestion_time {
+                record["ingestionTime"] = json!(ingestion_time);
+            }
+            return record;
+        };
+        let record = fields.iter().map(|(key, field)| {
+            let value =
+                match field {
+                    CsvColumn::TimestampIso => json!(timestamp
+                        .map(|t| local_time(t).to_rfc3339_opts(SecondsFormat::Millis, true))),
+                    CsvColumn::TimestampMs => json!(timestamp),
+                    CsvColumn::Datetime => json!(datetime),
+                    CsvColumn::Stream => json!(self.stream),
+                    CsvColumn::Message => json!(message),
+                    CsvColumn::EventId => json!(event_id),
+                };
+            (key.clone(), value)
+        });
+        Value::Object(record.collect())
+    }
+
+    fn csv_header(&self) -> String {
+        let names = self
+            .csv_columns
+            .iter()
+            .map(|c| {
+                c.to_possible_value()
+                    .expect("csv column")
+                    .get_name()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        to_csv(&names)
+    }
+
+    /// Compact JSON if the indent is 0, pretty printed otherwise
+    fn to_json(&self, value: &Value) -> String {
+        if self.json_indent == 0 {
+            return value.to_string();
+        }
+        let indent = " ".repeat(self.json_indent);
+        let mut buf = vec![];
+        let mut serializer =
+            Serializer::with_formatter(&mut buf, PrettyFormatter::with_indent(indent.as_bytes()));
+        value
+            .serialize(&mut serializer)
+            .expect("serialize json value");
+        String::from_utf8(buf).expect("json is utf-8")
+    }
+}
+
+/// Quotes the value if it's empty or has spaces, `=` or `"`, escapes quotes, `\` and newlines
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '=' || c == '"') {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// A CSV line, fields are quoted per RFC 4180 when needed
+fn to_csv<S: AsRef<[u8]>>(fields: &[S]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields).expect("write csv to memory");
+    String::from_utf8(writer.into_inner().expect("flush csv to memory")).expect("csv is utf-8")
+}
+
+/// Parses a delimiter with `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` escapes
+pub fn parse_delimiter(value: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .with_context(|| format!("invalid escape `\\x{hex}` in `{value}`"))?;
+                bytes.push(byte);
+            }
+            Some(c) => bail!("unknown escape `\\{c}` in `{value}`"),
+            None => bail!("trailing `\\` in `{value}`"),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Starts `$PAGER` or `less`, `LESS` defaults to `FRX` to exit if the output fits the screen
+pub fn spawn_pager() -> Option<Child> {
+    let pager = env::var("PAGER").unwrap_or("less".into());
+    if pager.is_empty() || pager == "cat" {
+        return None;
+    }
+    Command::new("sh")
+        .args(["-c", &pager])
+        .env("LESS", env::var("LESS").unwrap_or("FRX".into()))
+        .stdin(Stdio::piped())
+        .spawn()
+        .inspect_err(|e| debug!("cannot start pager `{pager}`: {e}"))
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_indent() {
+        let value = json!({"message": "m"});
+        assert_eq!(
+            Printer::new(OutputFormat::Json, "", 0, None).to_json(&value),
+            r#"{"message":"m"}"#
+        );
+        assert_eq!(
+            Printer::new(OutputFormat::Json, "", 4, None).to_json(&value),
+            "{\n    \"message\": \"m\"\n}"
+        );
+    }
+
+    #[test]
+    fn delimiter() {
+        assert_eq!(parse_delimiter("\\n").unwrap(), b"\n");
+        assert_eq!(parse_delimiter("\\0").unwrap(), b"\0");
+        assert_eq!(parse_delimiter("--\\x1e").unwrap(), b"--\x1e");
+        assert!(parse_delimiter("\\q").is_err());
+        assert!(parse_delimiter("\\").is_err());
+    }
+
+    #[test]
+    fn csv_header() {
+        let printer = Printer::new(OutputFormat::Csv, "", 0, None);
+        assert_eq!(
+            printer.csv_header(),
+            "timestamp_iso,timestamp_ms,stream,message\n"
+        );
+        let printer = printer.with_csv_columns(vec![CsvColumn::Datetime, CsvColumn::Message]);
+        assert_eq!(printer.csv_header(), "datetime,message\n");
+    }
+
+    #[test]
+    fn json_fields() {
+        let (key, field) = parse_json_field("ts=timestamp").unwrap();
+        assert_eq!((key.as_str(), field), ("ts", CsvColumn::TimestampMs));
+        let (key, field) = parse_json_field("message").unwrap();
+        assert_eq!((key.as_str(), field), ("message", CsvColumn::Message));
+        assert!(parse_json_field("x=level").is_err());
+        assert!(parse_json_field("=message").is_err());
+
+        let printer = Printer::new(OutputFormat::Ndjson, "", 0, None).with_json_fields(Some(vec![
+            ("msg".into(), CsvColumn::Message),
+            ("ts".into(), CsvColumn::TimestampMs),
+        ]));
+        assert_eq!(
+            printer
+                .json_record(Some(1), "d".into(), "m", &EventInfo::default())
+                .to_string(),
+            r#"{"msg":"m","ts":1}"#
+        );
+
+        let printer = Printer::new(OutputFormat::Ndjson, "", 0, None).with_event_id(true);
+        let info = EventInfo {
+            event_id: Some("1".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            printer
+                .json_record(Some(1), "d".into(), "m", &info)
+                .to_string(),
+            r#"{"timestamp":1,"datetime":"d","message":"m","event_id":"1"}"#
+        );
+        let info = EventInfo {
+            ingestion_time: Some(2),
+            ..info
+        };
+        assert_eq!(
+            printer
+                .json_record(Some(1), "d".into(), "m", &info)
+                .to_string(),
+            r#"{"timestamp":1,"datetime":"d","message":"m","event_id":"1","ingestionTime":2}"#
+        );
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            color_text("d", "|", "ERROR x"),
+            "\x1b[2md\x1b[0m|\x1b[31mERROR x\x1b[0m\n"
+        );
+        assert_eq!(color_text("d", " ", "x"), "\x1b[2md\x1b[0m x\n");
+    }
+
+    #[test]
+    fn flush_policy() {
+        let mut printer = Printer::new(OutputFormat::Text, "", 0, None).with_flush(FlushPolicy {
+            buffer_size: 10,
+            every: Some(3),
+            interval: None,
+        });
+        printer.buffer = b"12345".to_vec();
+        printer.buffered_records = 2;
+        assert!(!printer.flush_due());
+        printer.buffered_records = 3;
+        assert!(printer.flush_due());
+        printer.buffered_records = 1;
+        printer.buffer = b"1234567890".to_vec();
+        assert!(printer.flush_due());
+        printer.flush.every = None;
+        printer.flush.interval = Some(Duration::ZERO);
+        printer.buffer.clear();
+        assert!(printer.flush_due());
+    }
+
+    #[test]
+    fn json_array_lines() {
+        let output = |count| {
+            let mut printer = Printer::new(OutputFormat::Json, "d", 0, None).with_memory_output();
+            for m in ["a", "b"].iter().take(count) {
+                printer.print(Some(1), m, &EventInfo::default());
+            }
+            printer.finish().unwrap();
+            String::from_utf8(printer.into_output()).unwrap()
+        };
+        assert_eq!(output(0), "[]\n");
+        assert_eq!(
+            output(2).lines().collect::<Vec<_>>(),
+            [
+                "[",
+                r#"{"timestamp":1,"datetime":"d","message":"a"},"#,
+                r#"{"timestamp":1,"datetime":"d","message":"b"}"#,
+                "]"
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_output() {
+        let mut printer = Printer::new(OutputFormat::Ndjson, "%H", 0, None)
+            .with_json_fields(Some(vec![
+                ("m".into(), CsvColumn::Message),
+                ("s".into(), CsvColumn::Stream),
+            ]))
+            .with_memory_output();
+        assert!(printer.print(Some(0), "a", &EventInfo::default()));
+        printer.set_stream(Some("s1".into()));
+        assert!(printer.print(Some(0), "b", &EventInfo::default()));
+        printer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(printer.into_output()).unwrap(),
+            "{\"m\":\"a\",\"s\":null}\n{\"m\":\"b\",\"s\":\"s1\"}\n"
+        );
+        let mut printer = Printer::new(OutputFormat::Text, "x", 0, None)
+            .with_separator(" ")
+            .with_memory_output();
+        printer.print(Some(0), "a", &EventInfo::default());
+        printer.finish().unwrap();
+        assert_eq!(printer.into_output(), b"x a\n");
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(relative_time(0), "+0.000s");
+        assert_eq!(relative_time(61234), "+61.234s");
+        assert_eq!(relative_time(-5), "-0.005s");
+    }
+
+    #[test]
+    fn logfmt() {
+        assert_eq!(logfmt_value("abc"), "abc");
+        assert_eq!(logfmt_value(""), r#""""#);
+        assert_eq!(logfmt_value("a=b c"), r#""a=b c""#);
+        assert_eq!(logfmt_value("say \"hi\"\n"), r#""say \"hi\"\n""#);
+    }
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(
+            to_csv(&["a", "b,c", "say \"hi\"\nbye"]),
+            "a,\"b,c\",\"say \"\"hi\"\"\nbye\"\n"
+        );
+    }
+}
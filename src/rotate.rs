@@ -0,0 +1,156 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use log::debug;
+
+/// When to rotate the output file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotate {
+    Size(u64),
+    Age(Duration),
+}
+
+impl Rotate {
+    /// Parses a size with a `B`, `KB`, `MB` or `GB` suffix or a duration
+    pub fn parse(value: &str) -> Result<Self> {
+        let upper = value.to_ascii_uppercase();
+        for (suffix, multiplier) in [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)] {
+            if let Some(number) = upper.strip_suffix(suffix) {
+                return number
+                    .trim()
+                    .parse::<u64>()
+                    .map(|n| Rotate::Size(n * multiplier))
+                    .with_context(|| format!("cannot parse `{value}` as size"));
+            }
+        }
+        duration_str::parse(value)
+            .map(Rotate::Age)
+            .with_context(|| format!("cannot parse `{value}` as size or duration"))
+    }
+}
+
+/// Appends records to a file, rotated files are gzipped as `<stem>.<n>.<ext>.gz`
+pub struct RotatingWriter {
+    path: PathBuf,
+    rotate: Option<Rotate>,
+    file: BufWriter<File>,
+    /// the size of the current file, including the bytes written before it was opened
+    written: u64,
+    /// the modification time of the file when it was opened or the rotation time
+    opened: SystemTime,
+}
+
+impl RotatingWriter {
+    pub fn new(path: &Path, rotate: Option<Rotate>) -> Result<Self> {
+        let file = open(path)?;
+        let metadata = file
+            .get_ref()
+            .metadata()
+            .with_context(|| format!("cannot get metadata of output file {path:?}"))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotate,
+            file,
+            written: metadata.len(),
+            opened: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    /// Writes the whole record to the current file and rotates it after if required
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+        self.written += record.len() as u64;
+        let rotate = match self.rotate {
+            Some(Rotate::Size(size)) => self.written >= size,
+            Some(Rotate::Age(age)) => self.opened.elapsed().unwrap_or_default() >= age,
+            None => false,
+        };
+        if rotate {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = (1..)
+            .map(|n| self.rotated_path(n))
+            .find(|p| !p.exists())
+            .expect("free rotated file name");
+        debug!("rotate {:?} to {rotated:?}", self.path);
+        let mut encoder = GzEncoder::new(File::create(&rotated)?, Compression::default());
+        io::copy(&mut File::open(&self.path)?, &mut encoder)?;
+        encoder.finish()?;
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{stem}.{n}.{}.gz", ext.to_string_lossy()),
+            None => format!("{stem}.{n}.gz"),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+fn open(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("cannot create dirs for output file {path:?}"))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(BufWriter::new)
+        .with_context(|| format!("cannot open output file {path:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rotate() {
+        assert_eq!(Rotate::parse("10MB").unwrap(), Rotate::Size(10 << 20));
+        assert_eq!(Rotate::parse("512b").unwrap(), Rotate::Size(512));
+        assert_eq!(
+            Rotate::parse("1h").unwrap(),
+            Rotate::Age(Duration::from_secs(3600))
+        );
+        assert!(Rotate::parse("xMB").is_err());
+    }
+
+    #[test]
+    fn size_of_existing_file() {
+        let dir = std::env::temp_dir().join(format!("cw-axe-rotate-{}", std::process::id()));
+        let path = dir.join("out.log");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "12345678").unwrap();
+        let mut writer = RotatingWriter::new(&path, Some(Rotate::Size(10))).unwrap();
+        assert_eq!(writer.written, 8);
+        writer.write_record(b"abc").unwrap();
+        assert_eq!(writer.written, 0);
+        assert!(writer.rotated_path(1).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,26 +1,184 @@
-use crate::utils::format_opt_unix_ms;
+use std::{cmp::Reverse, io::stdin};
+
+use crate::cache::Cache;
+use crate::output::{print_line, print_text, ListPrinter};
+use crate::utils::{
+    format_opt_unix_ms, group_identifier, natural_cmp, shell_quote, ApiContext, Operation,
+};
+use crate::{time_arg, StreamsArgs};
 
 use super::utils::OptFuture;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
-use cloudwatchlogs::operation::describe_log_streams::builders::DescribeLogStreamsInputBuilder;
+use cloudwatchlogs::{
+    operation::describe_log_streams::builders::DescribeLogStreamsInputBuilder,
+    types::{LogStream, OrderBy},
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
+/// `log_command` is a command prefix to print a ready to run command instead of the stream name
 pub async fn print(
     client: &cloudwatchlogs::Client,
-    group: String,
-    prefix: Option<String>,
-    verbose: bool,
+    cache: &Cache,
+    args: &StreamsArgs,
     tab: bool,
+    log_command: Option<String>,
 ) -> Result<()> {
+    let mut printer = ListPrinter::new(args.format);
+    for s in list(client, cache, args).await? {
+        if let Some(name) = &s.log_stream_name {
+            let command = log_command
+                .as_ref()
+                .map(|command| format!("{command} {}", shell_quote(name)));
+            let text = command.clone().unwrap_or(name.clone());
+            let text = if !args.verbose {
+                text
+            } else {
+                format!(
+                    "{}{text} first {:?} last {:?}",
+                    if tab { "\t" } else { "" },
+                    format_opt_unix_ms(s.first_event_timestamp),
+                    format_opt_unix_ms(s.last_event_timestamp),
+                )
+            };
+            let mut record = record(&s);
+            if let Some(command) = command {
+                record["command"] = command.into();
+            }
+            printer.print(&text, record)?;
+        }
+    }
+    printer.finish()
+}
+
+/// Stream fields for structured output formats
+pub fn record(stream: &LogStream) -> Value {
+    json!({
+        "name": stream.log_stream_name,
+        "first_event_timestamp": stream.first_event_timestamp,
+        "last_event_timestamp": stream.last_event_timestamp,
+    })
+}
+
+/// Prints numbered streams and asks to choose one of them, returns the chosen stream name
+pub async fn pick(
+    client: &cloudwatchlogs::Client,
+    cache: &Cache,
+    args: &StreamsArgs,
+) -> Result<String> {
+    let names = list(client, cache, args)
+        .await?
+        .into_iter()
+        .filter_map(|s| s.log_stream_name)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        bail!("no streams found");
+    }
+    for (i, name) in names.iter().enumerate() {
+        print_line(format!("{:>4} {name}", i + 1))?;
+    }
+    print_text("stream number: ")?;
+    let mut line = String::new();
+    stdin()
+        .read_line(&mut line)
+        .context("cannot read stream number")?;
+    match line.trim().parse::<usize>() {
+        Ok(n) if (1..=names.len()).contains(&n) => Ok(names[n - 1].clone()),
+        _ => bail!(
+            "`{}` is not a number from 1 to {}",
+            line.trim(),
+            names.len()
+        ),
+    }
+}
+
+pub async fn list(
+    client: &cloudwatchlogs::Client,
+    cache: &Cache,
+    args: &StreamsArgs,
+) -> Result<Vec<LogStream>> {
+    let key = format!("streams {} {:?}", args.group, args.prefix);
+    let mut streams = if let Some(cached) = cache.get::<Vec<CachedStream>>(&key) {
+        cached.into_iter().map(LogStream::from).collect()
+    } else {
+        let streams = describe(client, args).await?;
+        cache.put(
+            &key,
+            streams.iter().map(CachedStream::from).collect::<Vec<_>>(),
+        );
+        streams
+    };
+    if let Some(active_within) = &args.active_within {
+        let now = time_arg::unix_now()?;
+        let since = now.as_millis() as i64
+            - duration_str::parse(active_within)
+                .with_context(|| format!("cannot parse `{active_within}` as duration"))?
+                .as_millis() as i64;
+        streams.retain(|s| s.last_event_timestamp.is_some_and(|t| t >= since));
+        streams.sort_by_key(|s| Reverse(s.last_event_timestamp));
+    } else if args.numeric_sort {
+        streams.sort_by(|l, r| {
+            natural_cmp(
+                l.log_stream_name.as_deref().unwrap_or_default(),
+                r.log_stream_name.as_deref().unwrap_or_default(),
+            )
+        });
+    } else {
+        streams.sort_by(|l, r| l.log_stream_name.cmp(&r.log_stream_name));
+    }
+    Ok(streams)
+}
+
+/// Returns names of up to `limit` streams with the most recent events
+pub async fn most_recent(
+    client: &cloudwatchlogs::Client,
+    group: &str,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let template = DescribeLogStreamsInputBuilder::default()
+        .log_group_identifier(group_identifier(group))
+        .order_by(OrderBy::LastEventTime)
+        .descending(true);
+    let mut names = vec![];
+    let mut opt_res = Some(template.clone().send_with(client).await);
+    while let Some(res) = opt_res {
+        let output = res.api_context(Operation::DescribeLogStreams, Some(group))?;
+        names.extend(
+            output
+                .log_streams
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|s| s.log_stream_name),
+        );
+        if names.len() >= limit {
+            names.truncate(limit);
+            break;
+        }
+        opt_res = output
+            .next_token
+            .map(|t| template.clone().next_token(t).send_with(client))
+            .resolve()
+            .await;
+    }
+    if names.is_empty() {
+        bail!("no streams found");
+    }
+    debug!("most recent streams: {names:?}");
+    Ok(names)
+}
+
+async fn describe(client: &cloudwatchlogs::Client, args: &StreamsArgs) -> Result<Vec<LogStream>> {
     let mut streams = vec![];
 
     let template = DescribeLogStreamsInputBuilder::default()
-        .set_log_group_name(Some(group))
-        .set_log_stream_name_prefix(prefix);
+        .log_group_identifier(group_identifier(&args.group))
+        .set_log_stream_name_prefix(args.prefix.clone());
 
     let mut opt_res = Some(template.clone().send_with(client).await);
     while let Some(res) = opt_res {
-        let output = res.context("describe log streams call failed")?;
+        let output = res.api_context(Operation::DescribeLogStreams, Some(&args.group))?;
         streams.append(&mut output.log_streams.unwrap());
         opt_res = output
             .next_token
@@ -28,20 +186,33 @@ pub async fn print(
             .resolve()
             .await;
     }
-    streams.sort_by(|l, r| l.log_stream_name.cmp(&r.log_stream_name));
-    for s in streams {
-        if let Some(name) = s.log_stream_name {
-            if !verbose {
-                println!("{name}");
-            } else {
-                println!(
-                    "{}{name} first {:?} last {:?}",
-                    if tab { "\t" } else { "" },
-                    format_opt_unix_ms(s.first_event_timestamp),
-                    format_opt_unix_ms(s.last_event_timestamp),
-                )
-            }
+    Ok(streams)
+}
+
+/// Stream fields kept in the cache
+#[derive(Serialize, Deserialize)]
+struct CachedStream {
+    name: Option<String>,
+    first_event_timestamp: Option<i64>,
+    last_event_timestamp: Option<i64>,
+}
+
+impl From<&LogStream> for CachedStream {
+    fn from(s: &LogStream) -> Self {
+        Self {
+            name: s.log_stream_name.clone(),
+            first_event_timestamp: s.first_event_timestamp,
+            last_event_timestamp: s.last_event_timestamp,
         }
     }
-    Ok(())
+}
+
+impl From<CachedStream> for LogStream {
+    fn from(s: CachedStream) -> Self {
+        LogStream::builder()
+            .set_log_stream_name(s.name)
+            .set_first_event_timestamp(s.first_event_timestamp)
+            .set_last_event_timestamp(s.last_event_timestamp)
+            .build()
+    }
 }
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+#[cfg(target_os = "linux")]
+use log::Record;
+use syslog::{Formatter3164, Logger, LoggerBackend};
+#[cfg(target_os = "linux")]
+use systemd_journal_logger::JournalLog;
+
+use crate::level::{self, Level};
+
+const TAG: &str = "cw-axe";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Facility {
+    User = 1,
+    Daemon = 3,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Severity {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl From<Level> for Severity {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => Severity::Err,
+            Level::Warn => Severity::Warning,
+            Level::Info => Severity::Info,
+            Level::Debug | Level::Trace => Severity::Debug,
+        }
+    }
+}
+
+/// Forwards events to the local syslog or journald
+pub struct Forwarder {
+    sink: Sink,
+    /// used when the level isn't detected in the message
+    severity: Severity,
+}
+
+enum Sink {
+    Syslog(Logger<LoggerBackend, Formatter3164>),
+    #[cfg(target_os = "linux")]
+    Journald(JournalLog),
+}
+
+impl Forwarder {
+    pub fn syslog(facility: Facility, severity: Severity) -> Result<Self> {
+        let formatter = Formatter3164 {
+            facility: facility.into(),
+            hostname: None,
+            process: TAG.into(),
+            pid: std::process::id(),
+        };
+        let logger = ::syslog::unix(formatter)
+            .map_err(|e| anyhow!("{e}"))
+            .context("cannot connect to syslog")?;
+        Ok(Self {
+            sink: Sink::Syslog(logger),
+            severity,
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn journald(facility: Facility, severity: Severity) -> Result<Self> {
+        let journal = JournalLog::empty()
+            .context("cannot connect to journald")?
+            .with_syslog_identifier(TAG.into())
+            .add_extra_field("SYSLOG_FACILITY", (facility as u8).to_string());
+        Ok(Self {
+            sink: Sink::Journald(journal),
+            severity,
+        })
+    }
+
+    /// Sends the event with the severity of the detected level
+    pub fn send(&mut self, message: &str) -> Result<()> {
+        let severity = level::detect(message).map_or(self.severity, Severity::from);
+        match &mut self.sink {
+            Sink::Syslog(logger) => match severity {
+                Severity::Emerg => logger.emerg(message),
+                Severity::Alert => logger.alert(message),
+                Severity::Crit => logger.crit(message),
+                Severity::Err => logger.err(message),
+                Severity::Warning => logger.warning(message),
+                Severity::Notice => logger.notice(message),
+                Severity::Info => logger.info(message),
+                Severity::Debug => logger.debug(message),
+            }
+            .map_err(|e| anyhow!("{e}")),
+            #[cfg(target_os = "linux")]
+            Sink::Journald(journal) => journal
+                .journal_send(
+                    &Record::builder()
+                        .args(format_args!("{message}"))
+                        .level(severity.into())
+                        .target(TAG)
+                        .build(),
+                )
+                .map_err(anyhow::Error::new),
+        }
+        .context("cannot send event to the log")
+    }
+}
+
+impl From<Facility> for ::syslog::Facility {
+    fn from(facility: Facility) -> Self {
+        use ::syslog::Facility::*;
+        match facility {
+            Facility::User => LOG_USER,
+            Facility::Daemon => LOG_DAEMON,
+            Facility::Local0 => LOG_LOCAL0,
+            Facility::Local1 => LOG_LOCAL1,
+            Facility::Local2 => LOG_LOCAL2,
+            Facility::Local3 => LOG_LOCAL3,
+            Facility::Local4 => LOG_LOCAL4,
+            Facility::Local5 => LOG_LOCAL5,
+            Facility::Local6 => LOG_LOCAL6,
+            Facility::Local7 => LOG_LOCAL7,
+        }
+    }
+}
+
+/// The journal priority is mapped from the log level, so severities above errors are errors
+#[cfg(target_os = "linux")]
+impl From<Severity> for ::log::Level {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Emerg | Severity::Alert | Severity::Crit | Severity::Err => Self::Error,
+            Severity::Warning => Self::Warn,
+            Severity::Notice | Severity::Info => Self::Info,
+            Severity::Debug => Self::Debug,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn severity() {
+        assert_eq!(Severity::from(Level::Warn), Severity::Warning);
+        assert_eq!(Severity::from(Level::Trace), Severity::Debug);
+        assert!(matches!(
+            ::syslog::Facility::from(Facility::Local0),
+            ::syslog::Facility::LOG_LOCAL0
+        ));
+        #[cfg(target_os = "linux")]
+        assert_eq!(::log::Level::from(Severity::Crit), ::log::Level::Error);
+    }
+}
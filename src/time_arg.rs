@@ -0,0 +1,310 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use chrono::{
+    DateTime, Datelike, Days, DurationRound, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Weekday,
+};
+use clap::ValueEnum;
+
+/// Boundary to align a time range to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Align {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Align {
+    fn span(self) -> TimeDelta {
+        match self {
+            Align::Minute => TimeDelta::minutes(1),
+            Align::Hour => TimeDelta::hours(1),
+            Align::Day => TimeDelta::days(1),
+        }
+    }
+}
+
+pub fn unix_now() -> Result<Duration> {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .context("cannot get unix time as duration")
+}
+
+/// Parses an absolute time or an offset back from now as unix time in ms
+pub fn parse(value: &str) -> Result<i64> {
+    parse_relative_to(value, &unix_now()?)
+}
+
+/// Parses an absolute time or an offset back from `unix_now` as unix time in ms
+pub fn parse_relative_to(value: &str, unix_now: &Duration) -> Result<i64> {
+    parse_as_epoch_ms(value)
+        .or_else(|_| {
+            duration_str::parse(value).map(|o| unix_now.saturating_sub(o).as_millis() as i64)
+        })
+        .or_else(|_| {
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S.%3f"))
+                .map_err(|_| 0)
+                .and_then(|n| {
+                    DateTime::from_timestamp_millis(unix_now.as_millis() as i64)
+                        .unwrap()
+                        .with_timezone(&Local)
+                        .with_time(n)
+                        .single()
+                        .map(|v| {
+                            if v.timestamp_millis() > (unix_now.as_millis() as i64) {
+                                v.checked_sub_days(Days::new(1)).unwrap().timestamp_millis()
+                            } else {
+                                v.timestamp_millis()
+                            }
+                        })
+                        .ok_or(0)
+                })
+        })
+        .or_else(|_| {
+            NaiveTime::parse_from_str(value, "%H:%MZ")
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%SZ"))
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S.%3fZ"))
+                .map_err(|_| 0)
+                .and_then(|n| {
+                    DateTime::from_timestamp_millis(unix_now.as_millis() as i64)
+                        .unwrap()
+                        .with_time(n)
+                        .single()
+                        .map(|v| {
+                            if v.timestamp_millis() > (unix_now.as_millis() as i64) {
+                                v.checked_sub_days(Days::new(1)).unwrap().timestamp_millis()
+                            } else {
+                                v.timestamp_millis()
+                            }
+                        })
+                        .ok_or(0)
+                })
+        })
+        .or_else(|_| DateTime::parse_from_rfc3339(value).map(|d| d.timestamp_millis()))
+        .or_else(|_| {
+            let now = DateTime::from_timestamp_millis(unix_now.as_millis() as i64)
+                .unwrap()
+                .with_timezone(&Local);
+            day_keyword(value, now)
+                .map(|v| v.timestamp_millis())
+                .ok_or(0)
+        })
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| 0)
+                .and_then(|d| {
+                    d.and_time(NaiveTime::MIN)
+                        .and_local_timezone(Local)
+                        .earliest()
+                        .map(|v| v.timestamp_millis())
+                        .ok_or(0)
+                })
+        })
+        .map_err(|_| {
+            anyhow::format_err!(
+                "failed to parse `{value}` as time, use one of:\n\
+                 * `10m` - offset from now\n\
+                 * `09:00` - local time of day\n\
+                 * `09:00Z` - UTC time of day\n\
+                 * `yesterday`, `mon` - local midnight of today, yesterday or the last weekday\n\
+                 * `2024-01-02` - local date\n\
+                 * `2024-01-02T03:04:05Z` - RFC 3339\n\
+                 * `1700000000` - Unix epoch time in seconds or milliseconds"
+            )
+        })
+}
+
+/// Midnight of `today`, `yesterday` or the last weekday, ex: `monday` or `mon`, which is
+/// today on the same weekday, in the time zone of `now`
+fn day_keyword<Tz: TimeZone>(value: &str, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let days_back = match value.to_ascii_lowercase().as_str() {
+        "today" => 0,
+        "yesterday" => 1,
+        value => {
+            let weekday = value.parse::<Weekday>().ok()?;
+            (now.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7
+        }
+    };
+    now.date_naive()
+        .checked_sub_days(Days::new(days_back.into()))?
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(now.timezone())
+        .earliest()
+}
+
+/// Parses a timestamp at the message start with the strftime format as unix ms,
+/// the time is local if the format has no offset
+pub fn parse_message_time(message: &str, format: &str) -> Option<i64> {
+    if let Ok((t, _)) = DateTime::parse_and_remainder(message, format) {
+        return Some(t.timestamp_millis());
+    }
+    NaiveDateTime::parse_and_remainder(message, format)
+        .ok()?
+        .0
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.timestamp_millis())
+}
+
+/// Formats of leading message timestamps stripped by default, the first matching one is used,
+/// so ones with an offset or comma separated ms go before their shorter variants
+pub const COMMON_PREFIX_FORMATS: [&str; 5] = [
+    "%+",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S,%3f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S%.f",
+];
+
+/// Removes a leading timestamp in one of the strftime formats and the following spaces
+pub fn strip_time_prefix<'a>(message: &'a str, formats: &[&str]) -> &'a str {
+    formats
+        .iter()
+        .find_map(|format| {
+            DateTime::parse_and_remainder(message, format)
+                .map(|(_, rest)| rest)
+                .or_else(|_| {
+                    NaiveDateTime::parse_and_remainder(message, format).map(|(_, rest)| rest)
+                })
+                .ok()
+        })
+        .map_or(message, str::trim_start)
+}
+
+/// Rounds the time down, or up if `up` is set, to the boundary in the time zone of `time`
+pub fn align<Tz: TimeZone>(time: DateTime<Tz>, to: Align, up: bool) -> DateTime<Tz> {
+    let down = time
+        .clone()
+        .duration_trunc(to.span())
+        .expect("span fits into the timestamp");
+    if up && down != time {
+        down + to.span()
+    } else {
+        down
+    }
+}
+
+fn parse_as_epoch_ms(candidate: &str) -> anyhow::Result<i64> {
+    let ms = candidate.parse::<i64>()?;
+    if ms > 946684800000 {
+        // 2000-01-01 in ms
+        Ok(ms)
+    } else {
+        Ok(ms * 1000)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn offset_or_duration() {
+        let ts = Duration::from_secs(
+            DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z")
+                .unwrap()
+                .timestamp() as u64,
+        );
+        // TODO: write proper test, maybe change local time zone or just copy implementation logic
+        // TODO: cover other cases
+        assert!(parse_relative_to("10:23", &ts).is_ok());
+        assert!(parse_relative_to("10:23:45", &ts).is_ok());
+        assert!(parse_relative_to("10:23:45.678", &ts).is_ok());
+
+        assert_eq!(parse_relative_to("1700000000", &ts).unwrap(), 1700000000000);
+        assert_eq!(
+            parse_relative_to("1700000000000", &ts).unwrap(),
+            1700000000000
+        );
+    }
+
+    #[test]
+    fn day_keywords() {
+        // Tuesday 01:30 local, 2024-01-01 23:30 UTC
+        let now = DateTime::parse_from_rfc3339("2024-01-02T01:30:00+02:00").unwrap();
+        let day = |value| day_keyword(value, now).map(|v| v.to_rfc3339());
+        let midnight = |date| Some(format!("{date}T00:00:00+02:00"));
+        assert_eq!(day("today"), midnight("2024-01-02"));
+        assert_eq!(day("Yesterday"), midnight("2024-01-01"));
+        assert_eq!(day("tue"), midnight("2024-01-02"));
+        assert_eq!(day("monday"), midnight("2024-01-01"));
+        assert_eq!(day("wed"), midnight("2023-12-27"));
+        assert_eq!(day("Sunday"), midnight("2023-12-31"));
+        assert_eq!(day("tomorrow"), None);
+
+        let ts = Duration::from_secs(now.timestamp() as u64);
+        assert!(parse_relative_to("yesterday", &ts).unwrap() < now.timestamp_millis());
+    }
+
+    #[test]
+    fn error_examples() {
+        let ts = Duration::from_secs(1700000000);
+        assert!(parse_relative_to("2024-01-02", &ts).is_ok());
+        let message = parse_relative_to("9am", &ts).unwrap_err().to_string();
+        for example in [
+            "`10m`",
+            "`09:00`",
+            "`09:00Z`",
+            "`yesterday`",
+            "`2024-01-02`",
+            "`2024-01-02T03:04:05Z`",
+            "`1700000000`",
+        ] {
+            assert!(message.contains(example), "{message}");
+        }
+    }
+
+    #[test]
+    fn message_time() {
+        assert_eq!(
+            parse_message_time(
+                "2024-01-02T03:04:05.678+00:00 INFO x",
+                "%Y-%m-%dT%H:%M:%S%.f%:z"
+            ),
+            Some(1704164645678)
+        );
+        assert!(parse_message_time("[02/Jan/2024:03:04:05] x", "[%d/%b/%Y:%H:%M:%S]").is_some());
+        assert_eq!(parse_message_time("INFO x", "%Y-%m-%d %H:%M:%S"), None);
+    }
+
+    #[test]
+    fn time_prefix() {
+        let strip = |m| strip_time_prefix(m, &COMMON_PREFIX_FORMATS);
+        assert_eq!(strip("2024-01-02T03:04:05.678Z INFO x"), "INFO x");
+        assert_eq!(strip("2024-01-02T03:04:05+01:00 x"), "x");
+        assert_eq!(strip("2024-01-02 03:04:05,678 x"), "x");
+        assert_eq!(strip("2024-01-02 03:04:05 x"), "x");
+        assert_eq!(
+            strip("INFO 2024-01-02 03:04:05 x"),
+            "INFO 2024-01-02 03:04:05 x"
+        );
+        assert_eq!(
+            strip_time_prefix("[03:04:05] x", &["[%H:%M:%S]"]),
+            "[03:04:05] x"
+        );
+        assert_eq!(
+            strip_time_prefix("[02/Jan/2024:03:04:05] x", &["[%d/%b/%Y:%H:%M:%S]"]),
+            "x"
+        );
+    }
+
+    #[test]
+    fn align_to() {
+        let time = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z").unwrap();
+        let at = |s| DateTime::parse_from_rfc3339(s).unwrap();
+        assert_eq!(
+            align(time, Align::Minute, false),
+            at("2024-01-02T03:04:00Z")
+        );
+        assert_eq!(align(time, Align::Minute, true), at("2024-01-02T03:05:00Z"));
+        assert_eq!(align(time, Align::Hour, false), at("2024-01-02T03:00:00Z"));
+        assert_eq!(align(time, Align::Hour, true), at("2024-01-02T04:00:00Z"));
+        assert_eq!(align(time, Align::Day, false), at("2024-01-02T00:00:00Z"));
+        assert_eq!(align(time, Align::Day, true), at("2024-01-03T00:00:00Z"));
+        let aligned = at("2024-01-02T03:00:00Z");
+        assert_eq!(align(aligned, Align::Hour, true), aligned);
+    }
+}
@@ -1,83 +1,731 @@
-use anyhow::Result;
-use egui::{FontFamily, Label, RichText, Widget};
+use std::{
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use egui::{
+    text::LayoutJob, Button, Color32, FontFamily, FontId, Key, Label, RichText, TextFormat,
+    TextStyle, Widget,
+};
 use egui_extras::Column;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    log::EventInfo,
+    output::{CsvColumn, OutputFormat, Printer},
+    utils::local_time,
+};
 
-pub fn run(lines: Lines) -> Result<()> {
+/// `filter` is the initial value of the filter box,
+/// `live` receives lines appended to the table, ex: from --follow polls,
+/// the window geometry and theme of the last run are restored by eframe persistence,
+/// visible lines are saved in the `save_format`
+pub fn run(
+    lines: Lines,
+    filter: String,
+    reload: Reload,
+    live: Option<Live>,
+    save_format: SaveFormat,
+) -> Result<()> {
+    let viewport = egui::ViewportBuilder::default()
+        .with_min_inner_size([600.0, 400.0])
+        .with_icon(
+            eframe::icon_data::from_png_bytes(&include_bytes!("../assets/icon-256.png")[..])
+                .expect("Failed to load icon"),
+        );
     let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_min_inner_size([600.0, 400.0])
-            .with_icon(
-                eframe::icon_data::from_png_bytes(&include_bytes!("../assets/icon-256.png")[..])
-                    .expect("Failed to load icon"),
-            ),
+        viewport,
+        persist_window: true,
         ..Default::default()
     };
 
     eframe::run_native(
         "cw-axe",
         native_options,
-        Box::new(|c| Box::new(Log::new(c, lines))),
+        Box::new(|c| Box::new(Log::new(c, lines, filter, reload, live, save_format))),
     )
     .map_err(|e| anyhow::Error::msg(e.to_string()))
 }
 
-pub type Lines = Vec<(String, String)>;
+pub type Lines = Vec<Line>;
+
+#[derive(Clone, Debug)]
+pub struct Line {
+    /// unix time in ms
+    pub timestamp: Option<i64>,
+    /// timestamp in the display format
+    pub datetime: String,
+    pub message: String,
+    /// None for lines which aren't from a stream, ex: --interleave-file lines
+    pub stream: Option<String>,
+}
+
+/// Output args of the CLI used to save the visible lines
+pub struct SaveFormat {
+    pub datetime_format: String,
+    pub separator: String,
+    pub json_indent: usize,
+    /// None for the default record
+    pub json_fields: Option<Vec<(String, CsvColumn)>>,
+}
+
+/// Format of copied timestamps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CopyFormat {
+    /// the same as displayed
+    Display,
+    /// RFC 3339 local time
+    Local,
+    /// RFC 3339 UTC time
+    Utc,
+    /// unix time in ms
+    Epoch,
+}
+
+impl CopyFormat {
+    const ALL: [CopyFormat; 4] = [
+        CopyFormat::Display,
+        CopyFormat::Local,
+        CopyFormat::Utc,
+        CopyFormat::Epoch,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            CopyFormat::Display => "as shown",
+            CopyFormat::Local => "RFC 3339 local",
+            CopyFormat::Utc => "RFC 3339 UTC",
+            CopyFormat::Epoch => "epoch ms",
+        }
+    }
+
+    fn format(self, line: &Line) -> String {
+        match (self, line.timestamp) {
+            (CopyFormat::Local, Some(t)) => {
+                local_time(t).to_rfc3339_opts(SecondsFormat::Millis, true)
+            }
+            (CopyFormat::Utc, Some(t)) => local_time(t)
+                .to_utc()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            (CopyFormat::Epoch, Some(t)) => t.to_string(),
+            _ => line.datetime.clone(),
+        }
+    }
+}
+
+/// eframe storage key of the theme, the window geometry is saved by eframe itself
+const DARK_KEY: &str = "dark";
+
+/// Resolved start and end as unix time in ms
+pub type Range = (i64, i64);
+
+/// Query parameters which can be changed in the UI, in the same format as CLI args
+#[derive(Clone, Debug)]
+pub struct Query {
+    pub start: String,
+    /// empty means now
+    pub end: String,
+    /// empty means no filter
+    pub filter: String,
+}
+
+/// Channels to a task which runs queries and sends back lines or an error
+pub struct Reload {
+    pub query: Query,
+    /// range of the shown lines
+    pub range: Range,
+    pub requests: UnboundedSender<Query>,
+    pub results: Receiver<Result<(Lines, Range), String>>,
+}
+
+/// Lines appended as they arrive, an error ends them
+pub type Live = Receiver<Result<Line, String>>;
 
 pub struct Log {
     lines: Lines,
+    /// None if lines aren't appended or the sender stopped
+    live: Option<Live>,
+    filter: String,
+    visible_indices: Vec<usize>,
+    reload: Reload,
+    loading: bool,
+    error: Option<String>,
+    /// Ctrl+F search box is shown
+    search_open: bool,
+    search: String,
+    /// visible row of the current match
+    search_row: Option<usize>,
+    /// the last search found nothing
+    no_match: bool,
+    /// row to scroll to on the next frame
+    scroll_to: Option<usize>,
+    /// timestamp and message of the last clicked row shown in a window
+    opened: Option<Line>,
+    copy_format: CopyFormat,
+    /// show messages in the proportional font instead of the monospace one
+    proportional: bool,
+    /// scale of the table rows and text
+    zoom: f32,
+    /// None if the save window is closed
+    save: Option<Save>,
+    /// lines are from more than one stream
+    many_streams: bool,
+    /// show the stream column if lines are from more than one stream
+    show_streams: bool,
+    /// the current theme, updated every frame and saved by eframe
+    dark: bool,
+    save_format: SaveFormat,
 }
 
+/// State of the window which saves the visible lines to a file
+#[derive(Default)]
+struct Save {
+    path: String,
+    json: bool,
+    /// result of the last save
+    status: Option<Result<String, String>>,
+}
+
+/// Table row height without zoom
+const ROW_HEIGHT: f32 = 20.;
+const ZOOM_MIN: f32 = 0.5;
+const ZOOM_MAX: f32 = 3.;
+
 impl Log {
-    pub fn new(_cc: &eframe::CreationContext<'_>, lines: Lines) -> Self {
-        Self { lines }
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        cc: &eframe::CreationContext<'_>,
+        lines: Lines,
+        filter: String,
+        reload: Reload,
+        live: Option<Live>,
+        save_format: SaveFormat,
+    ) -> Self {
+        if let Some(dark) = cc
+            .storage
+            .and_then(|s| eframe::get_value::<bool>(s, DARK_KEY))
+        {
+            cc.egui_ctx.set_visuals(if dark {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+        }
+        let mut log = Self {
+            lines,
+            live,
+            filter,
+            visible_indices: vec![],
+            reload,
+            loading: false,
+            error: None,
+            search_open: false,
+            search: String::new(),
+            search_row: None,
+            no_match: false,
+            scroll_to: None,
+            opened: None,
+            copy_format: CopyFormat::Display,
+            proportional: false,
+            zoom: 1.,
+            save: None,
+            many_streams: false,
+            show_streams: true,
+            dark: cc.egui_ctx.style().visuals.dark_mode,
+            save_format,
+        };
+        log.apply_filter();
+        // Ctrl+/Ctrl- zoom the table instead of the whole UI
+        cc.egui_ctx.options_mut(|o| o.zoom_with_keyboard = false);
+        log
     }
+
+    fn request_reload(&mut self) {
+        if self.reload.requests.send(self.reload.query.clone()).is_ok() {
+            self.loading = true;
+            self.error = None;
+        } else {
+            self.error = Some("query task is stopped".into());
+        }
+    }
+
+    fn receive_reload(&mut self, ctx: &egui::Context) {
+        if !self.loading {
+            return;
+        }
+        match self.reload.results.try_recv() {
+            Ok(Ok((lines, range))) => {
+                self.loading = false;
+                self.lines = lines;
+                self.reload.range = range;
+                self.apply_filter();
+            }
+            Ok(Err(e)) => {
+                self.loading = false;
+                self.error = Some(e);
+            }
+            Err(TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(100)),
+            Err(TryRecvError::Disconnected) => {
+                self.loading = false;
+                self.error = Some("query task is stopped".into());
+            }
+        }
+    }
+
+    fn receive_live(&mut self, ctx: &egui::Context) {
+        let Some(live) = &self.live else {
+            return;
+        };
+        loop {
+            match live.try_recv() {
+                Ok(Ok(line)) => {
+                    self.many_streams |= self
+                        .lines
+                        .first()
+                        .is_some_and(|first| first.stream != line.stream);
+                    if line.message.contains(&self.filter) {
+                        self.visible_indices.push(self.lines.len());
+                    }
+                    self.lines.push(line);
+                }
+                Ok(Err(e)) => {
+                    self.error = Some(e);
+                    self.live = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.live = None;
+                    return;
+                }
+            }
+        }
+        ctx.request_repaint_after(Duration::from_millis(500));
+    }
+
+    /// Moves the query range by its length to older or newer events and reloads
+    fn shift(&mut self, older: bool) {
+        let (start, end) = self.reload.range;
+        let length = end - start;
+        let (start, end) = if older {
+            (start - length, start)
+        } else {
+            (end, end + length)
+        };
+        let format = |t| local_time(t).to_rfc3339_opts(SecondsFormat::Millis, true);
+        self.reload.query.start = format(start);
+        self.reload.query.end = format(end);
+        self.request_reload();
+    }
+
+    /// Writes the visible lines in the text or JSON format of the CLI output
+    fn write_visible(&self, path: &str, json: bool) -> Result<()> {
+        let format = if json {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        };
+        let save_format = &self.save_format;
+        let mut printer = Printer::new(
+            format,
+            &save_format.datetime_format,
+            save_format.json_indent,
+            None,
+        )
+        .with_separator(&save_format.separator)
+        .with_json_fields(save_format.json_fields.clone())
+        .with_memory_output();
+        for &i in &self.visible_indices {
+            let line = &self.lines[i];
+            printer.set_stream(line.stream.clone());
+            printer.print(line.timestamp, &line.message, &EventInfo::default());
+        }
+        printer.finish()?;
+        std::fs::write(shellexpand::tilde(path).as_ref(), printer.into_output())
+            .context("cannot write the file")
+    }
+
+    /// Saves to the file chosen in the native dialog, the path can be typed
+    /// if the dialog isn't available
+    fn show_save(&mut self, ctx: &egui::Context) {
+        let Some(save) = &mut self.save else {
+            return;
+        };
+        let mut open = true;
+        let mut clicked = false;
+        egui::Window::new("save visible lines")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("path");
+                    egui::TextEdit::singleline(&mut save.path)
+                        .desired_width(300.)
+                        .ui(ui);
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut save.json, false, "text");
+                    ui.radio_value(&mut save.json, true, "JSON");
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Save as…").clicked() {
+                        let (name, extension) = if save.json {
+                            ("JSON", "json")
+                        } else {
+                            ("text", "log")
+                        };
+                        if let Some(path) = rfd::FileDialog::new()
+                            .set_file_name(format!("cw-axe.{extension}"))
+                            .add_filter(name, &[extension])
+                            .save_file()
+                        {
+                            save.path = path.to_string_lossy().into_owned();
+                            clicked = true;
+                        }
+                    }
+                    clicked |= ui
+                        .add_enabled(!save.path.is_empty(), Button::new("Save"))
+                        .clicked();
+                });
+                match &save.status {
+                    Some(Ok(status)) => {
+                        ui.label(status);
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(Color32::RED, e);
+                    }
+                    None => {}
+                }
+            });
+        if !open {
+            self.save = None;
+        } else if clicked {
+            let (path, json) = (save.path.clone(), save.json);
+            let status = match self.write_visible(&path, json) {
+                Ok(()) => Ok(format!("saved {} lines", self.visible_indices.len())),
+                Err(e) => Err(format!("cannot write {path}: {e:#}")),
+            };
+            if let Some(save) = &mut self.save {
+                save.status = Some(status);
+            }
+        }
+    }
+
+    /// Shows the full message of the clicked row, the window is reused for the next click
+    fn show_opened(&mut self, ctx: &egui::Context) {
+        let Some(line) = &self.opened else {
+            return;
+        };
+        let message = &line.message;
+        let mut open = true;
+        egui::Window::new("message")
+            .open(&mut open)
+            .default_size([600., 300.])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.monospace(&line.datetime);
+                    if let Some(stream) = &line.stream {
+                        ui.monospace(stream);
+                    }
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = message.clone());
+                    }
+                    if ui.button("Copy timestamp").clicked() {
+                        ui.output_mut(|o| o.copied_text = self.copy_format.format(line));
+                    }
+                    if ui.button("Copy row").clicked() {
+                        let timestamp = self.copy_format.format(line);
+                        ui.output_mut(|o| o.copied_text = format!("{timestamp} {message}"));
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    Label::new(RichText::new(message).family(FontFamily::Monospace))
+                        .wrap(true)
+                        .selectable(true)
+                        .ui(ui);
+                });
+            });
+        if !open {
+            self.opened = None;
+        }
+    }
+
+    fn apply_filter(&mut self) {
+        self.many_streams = self
+            .lines
+            .iter()
+            .any(|line| line.stream != self.lines[0].stream);
+        self.visible_indices = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.message.contains(&self.filter))
+            .map(|(i, _)| i)
+            .collect();
+        self.search_row = None;
+    }
+
+    /// Selects the next visible row containing the search text, wraps around at the end
+    fn next_match(&mut self) {
+        let count = self.visible_indices.len();
+        let from = self.search_row.map_or(0, |row| row + 1);
+        self.search_row = (0..count)
+            .map(|offset| (from + offset) % count)
+            .find(|&row| {
+                self.lines[self.visible_indices[row]]
+                    .message
+                    .contains(&self.search)
+            });
+        self.no_match = self.search_row.is_none();
+        self.scroll_to = self.search_row;
+    }
+}
+
+/// Text in the font with the search matches highlighted
+fn highlight(text: &str, search: &str, font: FontId, ui: &egui::Ui) -> LayoutJob {
+    let normal = TextFormat::simple(font.clone(), ui.visuals().text_color());
+    let found = TextFormat {
+        background: Color32::YELLOW,
+        ..TextFormat::simple(font, Color32::BLACK)
+    };
+    let mut job = LayoutJob::default();
+    let mut rest = text;
+    while let Some(i) = rest.find(search) {
+        job.append(&rest[..i], 0., normal.clone());
+        job.append(&rest[i..i + search.len()], 0., found.clone());
+        rest = &rest[i + search.len()..];
+    }
+    job.append(rest, 0., normal);
+    job
 }
+
 impl eframe::App for Log {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, DARK_KEY, &self.dark);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.dark = ctx.style().visuals.dark_mode;
+        self.receive_reload(ctx);
+        self.receive_live(ctx);
+        let focus_search = ctx.input(|i| i.modifiers.command && i.key_pressed(Key::F));
+        if focus_search {
+            self.search_open = true;
+        }
+        ctx.input(|i| {
+            if !i.modifiers.command {
+                return;
+            }
+            if i.key_pressed(Key::Plus) || i.key_pressed(Key::Equals) {
+                self.zoom += 0.1;
+            } else if i.key_pressed(Key::Minus) {
+                self.zoom -= 0.1;
+            } else if i.key_pressed(Key::Num0) {
+                self.zoom = 1.;
+            }
+        });
+        self.zoom = self.zoom.clamp(ZOOM_MIN, ZOOM_MAX);
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 egui::widgets::global_dark_light_mode_buttons(ui);
+                ui.separator();
+                if ui.button("Save").clicked() {
+                    self.save.get_or_insert_with(Save::default);
+                }
+                ui.toggle_value(&mut self.proportional, "Aa")
+                    .on_hover_text("proportional font of messages");
+                egui::Slider::new(&mut self.zoom, ZOOM_MIN..=ZOOM_MAX)
+                    .fixed_decimals(1)
+                    .ui(ui)
+                    .on_hover_text("zoom, Ctrl+/Ctrl-/Ctrl+0");
+                egui::ComboBox::from_id_source("copy_format")
+                    .selected_text(self.copy_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in CopyFormat::ALL {
+                            ui.selectable_value(&mut self.copy_format, format, format.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("format of copied timestamps");
+                if self.many_streams {
+                    ui.toggle_value(&mut self.show_streams, "Stream")
+                        .on_hover_text("stream name column");
+                }
+                ui.separator();
+                if egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("filter")
+                    .ui(ui)
+                    .changed()
+                {
+                    self.apply_filter();
+                }
+                ui.label(format!(
+                    "{}/{}",
+                    self.visible_indices.len(),
+                    self.lines.len()
+                ));
+                if self.search_open {
+                    ui.separator();
+                    let response = egui::TextEdit::singleline(&mut self.search)
+                        .hint_text("search, Enter for the next match")
+                        .ui(ui);
+                    if focus_search {
+                        response.request_focus();
+                    }
+                    if response.changed() {
+                        self.search_row = None;
+                        self.no_match = false;
+                    }
+                    if response.lost_focus() {
+                        if ui.input(|i| i.key_pressed(Key::Enter)) && !self.search.is_empty() {
+                            self.next_match();
+                            response.request_focus();
+                        } else if ui.input(|i| i.key_pressed(Key::Escape)) {
+                            self.search_open = false;
+                        }
+                    }
+                    if self.no_match {
+                        ui.colored_label(Color32::RED, "no matches");
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                let query = &mut self.reload.query;
+                ui.label("start");
+                egui::TextEdit::singleline(&mut query.start)
+                    .desired_width(160.)
+                    .ui(ui);
+                ui.label("end");
+                egui::TextEdit::singleline(&mut query.end)
+                    .hint_text("now")
+                    .desired_width(160.)
+                    .ui(ui);
+                ui.label("filter pattern");
+                egui::TextEdit::singleline(&mut query.filter)
+                    .desired_width(200.)
+                    .ui(ui);
+                if ui
+                    .add_enabled(!self.loading && self.live.is_none(), Button::new("Reload"))
+                    .clicked()
+                {
+                    self.request_reload();
+                }
+                if ui
+                    .add_enabled(!self.loading && self.live.is_none(), Button::new("◀ Older"))
+                    .clicked()
+                {
+                    self.shift(true);
+                }
+                if ui
+                    .add_enabled(!self.loading && self.live.is_none(), Button::new("Newer ▶"))
+                    .clicked()
+                {
+                    self.shift(false);
+                }
+                if self.loading {
+                    ui.spinner();
+                }
+                if let Some(error) = &self.error {
+                    ui.colored_label(Color32::RED, error);
+                }
             });
         });
         egui::CentralPanel::default().show(ctx, |ui| {
-            egui_extras::TableBuilder::new(ui)
+            let show_streams = self.many_streams && self.show_streams;
+            let mut table = egui_extras::TableBuilder::new(ui)
                 .auto_shrink(egui::Vec2b::new(false, false))
                 .resizable(true)
-                .column(Column::auto().clip(true))
+                .column(Column::auto().clip(true));
+            if show_streams {
+                table = table.column(Column::auto().clip(true));
+            }
+            table = table
                 .column(Column::remainder().clip(true))
                 .max_scroll_height(f32::INFINITY)
-                .sense(egui::Sense::click())
-                .header(20.0, |mut header| {
+                // follows new lines unless scrolled up
+                .stick_to_bottom(self.live.is_some())
+                .sense(egui::Sense::click());
+            if let Some(row) = self.scroll_to.take() {
+                table = table.scroll_to_row(row, Some(egui::Align::Center));
+            }
+            let search = if self.search_open {
+                self.search.as_str()
+            } else {
+                ""
+            };
+            let zoomed = |style: TextStyle| {
+                let mut font = style.resolve(ctx.style().as_ref());
+                font.size *= self.zoom;
+                font
+            };
+            let header_font = zoomed(TextStyle::Body);
+            let timestamp_font = zoomed(TextStyle::Monospace);
+            let message_font = if self.proportional {
+                zoomed(TextStyle::Body)
+            } else {
+                timestamp_font.clone()
+            };
+            let row_height = ROW_HEIGHT * self.zoom;
+            table
+                .header(row_height, |mut header| {
                     header.col(|ui| {
-                        ui.strong("Timestamp");
+                        ui.label(
+                            RichText::new("Timestamp")
+                                .strong()
+                                .font(header_font.clone()),
+                        );
                     });
+                    if show_streams {
+                        header.col(|ui| {
+                            ui.label(RichText::new("Stream").strong().font(header_font.clone()));
+                        });
+                    }
                     header.col(|ui| {
-                        ui.strong("Message");
+                        ui.label(RichText::new("Message").strong().font(header_font.clone()));
                     });
                 })
                 .body(|body| {
-                    body.rows(20., self.lines.len(), |mut row| {
-                        let index = row.index();
+                    body.rows(row_height, self.visible_indices.len(), |mut row| {
+                        let index = self.visible_indices[row.index()];
+                        row.set_selected(self.search_row == Some(row.index()));
                         row.col(|ui| {
                             Label::new(
-                                RichText::new(&self.lines[index].0).family(FontFamily::Monospace),
+                                RichText::new(&self.lines[index].datetime)
+                                    .font(timestamp_font.clone()),
                             )
                             .wrap(false)
                             .ui(ui);
                         });
+                        if show_streams {
+                            row.col(|ui| {
+                                let stream =
+                                    self.lines[index].stream.as_deref().unwrap_or_default();
+                                Label::new(RichText::new(stream).font(timestamp_font.clone()))
+                                    .wrap(false)
+                                    .ui(ui);
+                            });
+                        }
                         row.col(|ui| {
-                            Label::new(
-                                RichText::new(&self.lines[index].1).family(FontFamily::Monospace),
-                            )
-                            .truncate(true)
-                            .ui(ui);
+                            let message = &self.lines[index].message;
+                            if !search.is_empty() && message.contains(search) {
+                                Label::new(highlight(message, search, message_font.clone(), ui))
+                                    .truncate(true)
+                                    .ui(ui);
+                            } else {
+                                Label::new(RichText::new(message).font(message_font.clone()))
+                                    .truncate(true)
+                                    .ui(ui);
+                            }
                         });
                         if row.response().clicked() {
-                            // TODO show window
-                            // egui::Window::new("message").show(ctx, |ui| {});
+                            self.opened = Some(self.lines[index].clone());
                         }
                     });
                 });
         });
+        self.show_opened(ctx);
+        self.show_save(ctx);
     }
 }
@@ -1,8 +1,11 @@
 use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
     future::Future,
     time::{Duration, SystemTime},
 };
 
+use aws_sdk_cloudwatchlogs::error::ProvideErrorMetadata;
 use chrono::{DateTime, Local};
 
 pub trait OptFuture<T, F: Future<Output = T>> {
@@ -33,3 +36,273 @@ pub fn format_opt_unix_ms(opt_unix_time_ms: Option<i64>) -> String {
         .map(|d| d.to_string())
         .unwrap_or_default()
 }
+
+/// Quotes the value with single quotes if it contains anything except safe for a shell chars
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@%+=,".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r#"'\''"#))
+    }
+}
+
+/// Returns the region of a log group ARN, `arn:<partition>:logs:<region>:<account>:log-group:<name>`
+pub fn arn_region(group: &str) -> Option<&str> {
+    let mut parts = group.splitn(5, ':');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("arn"), Some(_), Some("logs"), Some(region)) if !region.is_empty() => Some(region),
+        _ => None,
+    }
+}
+
+/// API operations with the IAM actions they need
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    DescribeLogGroups,
+    DescribeLogStreams,
+    GetLogEvents,
+    FilterLogEvents,
+}
+
+impl Operation {
+    fn iam_action(self) -> &'static str {
+        match self {
+            Operation::DescribeLogGroups => "logs:DescribeLogGroups",
+            Operation::DescribeLogStreams => "logs:DescribeLogStreams",
+            Operation::GetLogEvents => "logs:GetLogEvents",
+            Operation::FilterLogEvents => "logs:FilterLogEvents",
+        }
+    }
+
+    fn failed(self) -> &'static str {
+        match self {
+            Operation::DescribeLogGroups => "describe log groups call failed",
+            Operation::DescribeLogStreams => "describe log streams call failed",
+            Operation::GetLogEvents => "get log events failed",
+            Operation::FilterLogEvents => "filter log events failed",
+        }
+    }
+}
+
+pub trait ApiContext<T> {
+    /// Adds the failed operation to the error, with the IAM action it needs on the group
+    /// if the access is denied
+    fn api_context(self, operation: Operation, group: Option<&str>) -> anyhow::Result<T>;
+}
+
+impl<T, E> ApiContext<T> for Result<T, E>
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    fn api_context(self, operation: Operation, group: Option<&str>) -> anyhow::Result<T> {
+        self.map_err(|e| {
+            let context = match access_hint(&e, operation, group) {
+                Some(hint) => format!("{}, {hint}", operation.failed()),
+                None => operation.failed().to_string(),
+            };
+            anyhow::Error::new(e).context(context)
+        })
+    }
+}
+
+fn access_hint(
+    error: &impl ProvideErrorMetadata,
+    operation: Operation,
+    group: Option<&str>,
+) -> Option<String> {
+    if error.code() != Some("AccessDeniedException") {
+        return None;
+    }
+    let action = operation.iam_action();
+    Some(match group {
+        Some(group) => format!("access denied: you need {action} on log group `{group}`"),
+        None => format!("access denied: you need {action}"),
+    })
+}
+
+/// Group name or ARN without `:*` suffix, which isn't accepted as a log group identifier
+pub fn group_identifier(group: &str) -> &str {
+    if arn_region(group).is_some() {
+        group.strip_suffix(":*").unwrap_or(group)
+    } else {
+        group
+    }
+}
+
+/// Replaces control chars except new line and tab with caret notation, ex: `^@`, `^[`,
+/// and C1 control chars with `\u{..}`
+pub fn escape_control(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\n' | '\t' => escaped.push(c),
+            '\0'..='\x1f' => {
+                escaped.push('^');
+                escaped.push((c as u8 + b'@') as char);
+            }
+            '\x7f' => escaped.push_str("^?"),
+            c if c.is_control() => escaped.push_str(&c.escape_unicode().to_string()),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Cuts the value to `max` chars and adds the original length
+pub fn truncate_chars(value: String, max: usize) -> String {
+    match value.char_indices().nth(max) {
+        Some((end, _)) => format!("{}… [{} chars]", &value[..end], value.chars().count()),
+        None => value,
+    }
+}
+
+/// Compares strings with runs of digits compared as numbers, ex: `s-2` is before `s-10`,
+/// equal numbers with different leading zeros are ordered lexically
+pub fn natural_cmp(l: &str, r: &str) -> Ordering {
+    let (mut ls, mut rs) = (l, r);
+    loop {
+        let (Some(lc), Some(rc)) = (ls.chars().next(), rs.chars().next()) else {
+            return (!ls.is_empty()).cmp(&!rs.is_empty()).then_with(|| l.cmp(r));
+        };
+        if lc.is_ascii_digit() && rc.is_ascii_digit() {
+            let ld = ls.len() - ls.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let rd = rs.len() - rs.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let ln = ls[..ld].trim_start_matches('0');
+            let rn = rs[..rd].trim_start_matches('0');
+            let ordering = ln.len().cmp(&rn.len()).then_with(|| ln.cmp(rn));
+            if ordering.is_ne() {
+                return ordering;
+            }
+            (ls, rs) = (&ls[ld..], &rs[rd..]);
+        } else if lc != rc {
+            return lc.cmp(&rc);
+        } else {
+            (ls, rs) = (&ls[lc.len_utf8()..], &rs[rc.len_utf8()..]);
+        }
+    }
+}
+
+/// Reorders events by timestamp, an event is released once an event newer by the window is seen
+pub struct SortWindow<T> {
+    window_ms: i64,
+    newest: i64,
+    sequence: u64,
+    heap: BinaryHeap<Reverse<(i64, u64, T)>>,
+}
+
+impl<T: Ord> SortWindow<T> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window_ms: window.as_millis() as i64,
+            newest: i64::MIN,
+            sequence: 0,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Adds the event and returns events which are out of the window, sorted by timestamp
+    pub fn push(&mut self, timestamp: Option<i64>, event: T) -> Vec<(Option<i64>, T)> {
+        let timestamp = timestamp.unwrap_or_default();
+        self.newest = self.newest.max(timestamp);
+        self.heap.push(Reverse((timestamp, self.sequence, event)));
+        self.sequence += 1;
+        let mut released = vec![];
+        while let Some(Reverse((t, _, _))) = self.heap.peek() {
+            if *t > self.newest.saturating_sub(self.window_ms) {
+                break;
+            }
+            let Reverse((t, _, m)) = self.heap.pop().unwrap();
+            released.push((Some(t), m));
+        }
+        released
+    }
+
+    /// Returns all remaining events sorted by timestamp
+    pub fn drain(mut self) -> Vec<(Option<i64>, T)> {
+        let mut released = vec![];
+        while let Some(Reverse((t, _, m))) = self.heap.pop() {
+            released.push((Some(t), m));
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn access_hints() {
+        use aws_sdk_cloudwatchlogs::error::ErrorMetadata;
+        let denied = ErrorMetadata::builder()
+            .code("AccessDeniedException")
+            .build();
+        assert_eq!(
+            access_hint(&denied, Operation::FilterLogEvents, Some("g")).unwrap(),
+            "access denied: you need logs:FilterLogEvents on log group `g`"
+        );
+        assert_eq!(
+            access_hint(&denied, Operation::DescribeLogGroups, None).unwrap(),
+            "access denied: you need logs:DescribeLogGroups"
+        );
+        let throttled = ErrorMetadata::builder().code("ThrottlingException").build();
+        assert!(access_hint(&throttled, Operation::GetLogEvents, Some("g")).is_none());
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["s-10", "s-2", "s-1b", "s-1a", "s", "s-02", "t-0", "s-1"];
+        names.sort_by(|l, r| natural_cmp(l, r));
+        assert_eq!(
+            names,
+            vec!["s", "s-1", "s-1a", "s-1b", "s-02", "s-2", "s-10", "t-0"]
+        );
+        let long = "9".repeat(30);
+        assert_eq!(natural_cmp(&long, &format!("1{long}")), Ordering::Less);
+    }
+
+    #[test]
+    fn sort_window() {
+        let mut w = SortWindow::<Option<String>>::new(Duration::from_millis(100));
+        assert!(w.push(Some(1000), Some("a".into())).is_empty());
+        assert!(w.push(Some(950), Some("b".into())).is_empty());
+        assert_eq!(
+            w.push(Some(1100), Some("c".into())),
+            vec![
+                (Some(950), Some("b".into())),
+                (Some(1000), Some("a".into()))
+            ]
+        );
+        assert_eq!(w.drain(), vec![(Some(1100), Some("c".into()))]);
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            escape_control("a\0b\x1b[31mc\x7f\u{9b}\td\n"),
+            "a^@b^[[31mc^?\\u{9b}\td\n"
+        );
+    }
+
+    #[test]
+    fn truncate() {
+        assert_eq!(truncate_chars("abc".into(), 3), "abc");
+        assert_eq!(truncate_chars("äbcd".into(), 2), "äb… [4 chars]");
+    }
+
+    #[test]
+    fn arn() {
+        let arn = "arn:aws:logs:eu-west-1:123456789012:log-group:/my/group:*";
+        assert_eq!(arn_region(arn), Some("eu-west-1"));
+        assert_eq!(
+            group_identifier(arn),
+            "arn:aws:logs:eu-west-1:123456789012:log-group:/my/group"
+        );
+        assert_eq!(arn_region("/my/group:*"), None);
+        assert_eq!(group_identifier("/my/group:*"), "/my/group:*");
+    }
+}